@@ -1,7 +1,28 @@
-use crate::module::{manager::ModMgr, mod_file::ModFile};
-use crate::{logd, loge, logi};
+use crate::module::{
+    coverage,
+    env::BuildEnv,
+    install::{self, InstallDirs, InstallManifest},
+    lint::LintTool,
+    manager::ModMgr,
+    mod_file::ModFile,
+    template::{self, TemplateKind},
+};
+use crate::utils::probe::{find_program, is_probeable_flag, probe_key, CompilerKind, ProbeCache};
+use crate::utils::{
+    diagnostics::{self, MessageFormat},
+    git,
+    progress::format_duration,
+    yaml::{Config, ModDep, Sanitizer},
+    yaml_edit,
+};
+use crate::{logd, logi, logw};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 enum RunStatus {
@@ -10,11 +31,127 @@ enum RunStatus {
     Unknown,
 }
 
+/// 构建完成后对模块执行的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildAction {
+    Build,
+    Run,
+    Test,
+}
+
+/// --message-format json 在 hk lint 结束时输出的消息
+#[derive(Serialize)]
+struct LintFinished {
+    reason: &'static str,
+    success: bool,
+    checks: usize,
+    warnings: BTreeMap<String, usize>,
+    errors: BTreeMap<String, usize>,
+}
+
+/// --message-format json 在 hk test 结束时输出的消息
+#[derive(Serialize)]
+struct TestFinished {
+    reason: &'static str,
+    success: bool,
+    passed: usize,
+    failed: Vec<String>,
+}
+
+/// 构建失败的模块：错误信息与失败步骤捕获的输出
+#[derive(Debug)]
+struct BuildFailure {
+    module: String,
+    error: String,
+    diagnostics: Vec<String>,
+}
+
+/// 一次构建中各模块的结果
+#[derive(Debug, Default)]
+struct BuildSummary {
+    built: Vec<String>,
+    /// 使用预编译产物而未构建的模块
+    skipped: Vec<String>,
+    /// 因依赖构建失败而跳过的模块及其失败的依赖
+    blocked: Vec<(String, String)>,
+    failures: Vec<BuildFailure>,
+}
+
+/// --message-format json 在构建结束时输出的消息
+#[derive(Serialize)]
+struct BuildFinished {
+    reason: &'static str,
+    success: bool,
+    built: usize,
+    skipped: usize,
+    failed: Vec<String>,
+    /// 各模块的警告数
+    warnings: BTreeMap<String, usize>,
+}
+
+impl BuildSummary {
+    fn report(&self, env: &BuildEnv, elapsed: Duration) {
+        let warnings = env.diagnostics.warning_counts();
+        let failed: Vec<String> = self.failures.iter().map(|f| f.module.clone()).collect();
+        if env.message_format == MessageFormat::Json {
+            let _ = diagnostics::emit_json(&BuildFinished {
+                reason: "build-finished",
+                success: failed.is_empty(),
+                built: self.built.len(),
+                skipped: self.skipped.len() + self.blocked.len(),
+                failed: failed.clone(),
+                warnings: warnings.clone(),
+            });
+        }
+
+        let warning_count: usize = warnings.values().sum();
+        let mut line = format!(
+            "{} {} build: {} built, {} skipped, {} failed{} in {}",
+            if self.failures.is_empty() {
+                "Finished"
+            } else {
+                "Failed"
+            },
+            env.profile.as_str(),
+            self.built.len(),
+            self.skipped.len() + self.blocked.len(),
+            self.failures.len(),
+            match warning_count {
+                0 => String::new(),
+                1 => ", 1 warning".to_string(),
+                n => format!(", {} warnings", n),
+            },
+            format_duration(elapsed)
+        );
+        if !failed.is_empty() {
+            line.push_str(&format!(" ({})", failed.join(", ")));
+        }
+        env.progress.println(&line);
+    }
+
+    /// --keep-going 结束时列出所有失败的模块及其诊断输出，以及被跳过的依赖方
+    fn report_failures(&self) {
+        eprintln!("error: {} module(s) failed to build:", self.failures.len());
+        for failure in &self.failures {
+            eprintln!("  {}: {}", failure.module, failure.error);
+            for line in failure.diagnostics.iter().flat_map(|d| d.lines()) {
+                eprintln!("      {}", line);
+            }
+        }
+        for (module, dep) in &self.blocked {
+            eprintln!("  {}: skipped, depends on failed module {}", module, dep);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CmdCtx {
     status: RunStatus,
     mods: Option<ModMgr>,
     cur_mod: Option<ModFile>,
+    env: BuildEnv,
+    /// 项目根目录（模块目录下运行时为其上级目录）
+    root: PathBuf,
 }
 
 impl CmdCtx {
@@ -23,86 +160,824 @@ impl CmdCtx {
             status: RunStatus::Unknown,
             mods: None,
             cur_mod: None,
+            env: BuildEnv::default(),
+            root: PathBuf::from("."),
         }
     }
 
-    /// 检测当前工具运行命令环境是 项目根目录 还是 模块根目录
+    /// 设置构建环境，需在 `detect_env` 之前调用以便条件块正确求值
+    pub fn set_env(&mut self, env: BuildEnv) {
+        self.env = env;
+    }
+
+    /// 检测当前工具运行命令环境是 项目根目录 还是 模块根目录，
+    /// 并探测各模块所用编译器是否可用
     pub fn detect_env(&mut self) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        self.resolve_sanitizers()?;
+        self.probe_compilers()?;
+        self.check_compilers()
+    }
+
+    /// 检测项目结构并建立模块依赖图
+    pub fn detect_layout(&mut self) -> Result<(), Box<dyn Error>> {
         let run_dir = PathBuf::from("."); //获取当前文件夹路径
         logi!("detect_env run_dir:{:#?}", run_dir);
         let mut manage = ModMgr::new();
 
-        //如果是项目根目录
-        if manage.build_dep_graph(&run_dir).is_ok() {
-            logd!("{:#?} try build_dep_graph success", run_dir);
-            self.status = RunStatus::ProjectRoot;
+        let mut mod_ = ModFile::new();
+
+        //如果是mod目录
+        if ModFile::is_mod_dir(&run_dir) {
+            mod_.load(&run_dir, &self.env)?;
+            logd!("{:#?} try get mod info success", run_dir);
+            self.status = RunStatus::ModRoot;
+            self.cur_mod = Some(mod_);
+            self.root = run_dir.join("..");
+            manage.build_dep_graph(&self.root, &self.env)?;
             self.mods = Some(manage);
         } else {
-            let mut mod_ = ModFile::new();
-
-            //如果是mod目录
-            if mod_.load(&run_dir).is_ok() {
-                logd!("{:#?} try get mod info success", run_dir);
-                self.status = RunStatus::ModRoot;
-                self.cur_mod = Some(mod_);
-                manage.build_dep_graph(&run_dir.join(".."))?;
-                self.mods = Some(manage);
-            } else {
+            //否则按项目根目录处理，外部依赖解析失败等错误直接返回
+            if let Err(e) = manage.build_dep_graph(&run_dir, &self.env) {
                 self.status = RunStatus::Unknown;
-                return Err("err hk project struct".into());
+                return Err(format!("err hk project struct: {}", e).into());
+            }
+            logd!("{:#?} try build_dep_graph success", run_dir);
+            self.status = RunStatus::ProjectRoot;
+            self.root = run_dir;
+            self.mods = Some(manage);
+        }
+        Ok(())
+    }
+
+    /// 未通过 --sanitize 指定时，合并各模块配置的 sanitize 并应用到整个依赖图，
+    /// 以启用后的构建目录重新加载模块；仅 build/run/test 使用模块配置的 sanitize
+    fn resolve_sanitizers(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.env.sanitize.is_empty() || !self.env.config_sanitize {
+            return Ok(());
+        }
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let mut wanted = Vec::new();
+        for modfile in mods.project_map.indices.values() {
+            wanted.extend(modfile.config()?.sanitize.iter().copied());
+        }
+        if wanted.is_empty() {
+            return Ok(());
+        }
+        wanted.sort();
+        wanted.dedup();
+        Sanitizer::check(&wanted)?;
+        logi!("Sanitizers from module configs: {:?}", wanted);
+        self.env.sanitize = wanted;
+        self.detect_layout()
+    }
+
+    /// 探测所有模块用到的编译器、-std 与编译参数，结果缓存在 <project>/.hk/probe.yaml
+    fn probe_compilers(&mut self) -> Result<(), Box<dyn Error>> {
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let mut cache = ProbeCache::load(&self.root);
+        let mut used = BTreeMap::new();
+
+        for name in &mods.project_map.mod_names {
+            let config = mods.project_map.indices[name].config()?;
+            let cc = &config.compiler.cc;
+            let clang = cache.compiler(cc, &[]).kind == CompilerKind::Clang;
+            let target_args = config.compiler.target_args(clang);
+            let info = cache.compiler(cc, &target_args);
+            if info.found() {
+                info.supports_std(&config.std);
+                for flag in config
+                    .compiler
+                    .flags
+                    .iter()
+                    .filter(|f| is_probeable_flag(f))
+                {
+                    info.supports_flag(flag);
+                }
+                if self.env.message_format == MessageFormat::Json && info.kind == CompilerKind::Gcc
+                {
+                    info.supports_flag(diagnostics::JSON_FLAG);
+                }
+            }
+            used.insert(probe_key(cc, &target_args), info.clone());
+        }
+
+        // 缓存写入失败不影响构建，dry-run 时不写缓存
+        if !self.env.dry_run {
+            if let Err(e) = cache.save() {
+                logw!("Failed to save probe cache: {}", e);
             }
         }
+        self.env.compilers = used;
         Ok(())
     }
 
+    /// 根据探测结果检查每个模块的编译器、-std 与参数，给出明确的错误
+    fn check_compilers(&self) -> Result<(), Box<dyn Error>> {
+        let problems = self.compiler_problems()?;
+        if let Some(first) = problems.first() {
+            return Err(format!("{} (run `hk doctor` for details)", first).into());
+        }
+        Ok(())
+    }
+
+    /// 收集所有模块的编译器问题描述
+    fn compiler_problems(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let mut problems = Vec::new();
+
+        for name in &mods.project_map.mod_names {
+            let config = mods.project_map.indices[name].config()?;
+            let cc = &config.compiler.cc;
+            let Some(info) = self.env.compiler_info(&config.compiler) else {
+                continue;
+            };
+            if !info.found() {
+                problems.push(format!(
+                    "Compiler '{}' required by module '{}' was not found in PATH",
+                    cc, name
+                ));
+                continue;
+            }
+            let described = format!("{} {} ({})", info.kind.as_str(), info.version, cc);
+            if info.stds.get(&config.std) == Some(&false) {
+                problems.push(format!(
+                    "{} does not support -std={} required by module '{}'",
+                    described, config.std, name
+                ));
+            }
+            for flag in &config.compiler.flags {
+                if info.flags.get(flag) == Some(&false) {
+                    problems.push(format!(
+                        "{} does not support flag {} required by module '{}'",
+                        described, flag, name
+                    ));
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    ///诊断命令：输出主机、编译器与相关工具的探测结果
+    pub fn doctor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        self.probe_compilers()?;
+
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        println!("host:     {}", self.env.host);
+        println!(
+            "project:  {} ({} modules)",
+            self.root.canonicalize()?.display(),
+            mods.project_map.mod_names.len()
+        );
+        if let Some(toolchain) = &self.env.toolchain {
+            println!("toolchain: {}", toolchain.name);
+        }
+
+        for (cc, info) in &self.env.compilers {
+            println!();
+            println!("compiler: {}", cc);
+            match &info.path {
+                Some(path) => println!("  path:      {}", path.display()),
+                None => {
+                    println!("  path:      NOT FOUND");
+                    continue;
+                }
+            }
+            println!("  kind:      {} {}", info.kind.as_str(), info.version);
+            for (std, ok) in &info.stds {
+                println!(
+                    "  std:       {} {}",
+                    std,
+                    if *ok { "ok" } else { "unsupported" }
+                );
+            }
+            for (flag, ok) in &info.flags {
+                println!(
+                    "  flag:      {} {}",
+                    flag,
+                    if *ok { "ok" } else { "unsupported" }
+                );
+            }
+
+            // 同一编译器下各模块用到的归档工具与链接器
+            let mut tools = BTreeMap::new();
+            let mut users = Vec::new();
+            for name in &mods.project_map.mod_names {
+                let compiler = &mods.project_map.indices[name].config()?.compiler;
+                if compiler.cc == info.cc {
+                    users.push(name.as_str());
+                    tools.insert(compiler.archiver(), "archiver");
+                    tools.insert(compiler.linker().to_string(), "linker");
+                }
+            }
+            for (tool, role) in &tools {
+                let state = match find_program(tool) {
+                    Some(path) => path.display().to_string(),
+                    None => "NOT FOUND".to_string(),
+                };
+                println!("  {:<11}{} -> {}", format!("{}:", role), tool, state);
+            }
+            println!("  used by:   {}", users.join(", "));
+        }
+
+        let problems = self.compiler_problems()?;
+        println!();
+        if problems.is_empty() {
+            println!("No problems found");
+            Ok(())
+        } else {
+            for problem in &problems {
+                println!("problem: {}", problem);
+            }
+            Err(format!("{} problem(s) found", problems.len()).into())
+        }
+    }
+
     ///构建命令，构建当前模块以及其所依赖的其余模块
     ///基于其所在目录区分逻辑
-    pub fn build(&mut self, is_run: bool) -> Result<(), Box<dyn Error>> {
-        match self.status {
-            RunStatus::ModRoot => {
-                let mods = self.mods.as_mut().unwrap();
-                loop {
-                    let mut next = mods.get_next_buildable()?;
-                    if next.is_empty() {
-                        break;
-                    }
-                    for mod_ in &mut next {
-                        mod_.build()?;
-                        if is_run {
-                            mod_.run()?;
-                        }
-                        if mod_.name == self.cur_mod.as_ref().unwrap().name {
-                            return Ok(());
+    pub fn build(&mut self, action: BuildAction) -> Result<(), Box<dyn Error>> {
+        let cur_name = match self.status {
+            RunStatus::ModRoot => Some(self.cur_mod.as_ref().unwrap().name.clone()),
+            RunStatus::ProjectRoot => None,
+            RunStatus::Unknown => {
+                return Err("Err project".into());
+            }
+        };
+
+        let mods = self.mods.as_mut().unwrap();
+        mods.configure(&self.env)?;
+
+        // 模块目录下只构建当前模块及其依赖
+        let wanted = cur_name.as_ref().map(|name| mods.with_deps(name));
+        let is_wanted = |name: &str| wanted.as_ref().is_none_or(|w| w.contains(name));
+        let total = mods
+            .project_map
+            .mod_names
+            .iter()
+            .filter(|name| is_wanted(name))
+            .map(|name| {
+                let tests =
+                    action == BuildAction::Test && cur_name.as_ref().is_none_or(|cur| cur == name);
+                mods.project_map.indices[name].step_count(tests)
+            })
+            .sum();
+        let progress = self.env.progress.clone();
+        if self.env.dry_run {
+            progress.disable();
+        }
+        progress.start(total);
+
+        let start = Instant::now();
+        let timings = self.env.timings.clone();
+        let mut summary = BuildSummary::default();
+        let mut test_results = Vec::new();
+        // 未启用 --keep-going 时第一个失败的错误，汇总与计时写出后返回
+        let mut fatal: Option<Box<dyn Error>> = None;
+        let mut round = 0;
+        // 构建失败或因此被跳过的模块，其依赖方都要跳过
+        let mut broken: HashSet<String> = HashSet::new();
+        'build: loop {
+            let round_start = Instant::now();
+            let mut next = mods.get_next_buildable()?;
+            if next.is_empty() {
+                break;
+            }
+            next.retain(|m| is_wanted(&m.name));
+            round += 1;
+            let round_name = format!(
+                "round {}: {}",
+                round,
+                next.iter()
+                    .map(|m| m.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            for mod_ in &mut next {
+                let deps = mod_.config()?.dep.dep_names();
+                if let Some(dep) = deps.into_iter().find(|d| broken.contains(d)) {
+                    progress.println(&format!(
+                        "   Skipped {} (depends on failed module {})",
+                        mod_.name, dep
+                    ));
+                    broken.insert(mod_.name.clone());
+                    summary.blocked.push((mod_.name.clone(), dep));
+                    continue;
+                }
+                if mod_.prebuilt {
+                    summary.skipped.push(mod_.name.clone());
+                } else {
+                    let mod_start = Instant::now();
+                    // 丢弃之前步骤（如测试编译）留下的输出，只保留本模块的
+                    self.env.take_failures();
+                    if let Err(e) = mod_.build(&self.env) {
+                        timings.record("module", &mod_.name, &mod_.name, mod_start, false);
+                        summary.failures.push(BuildFailure {
+                            module: mod_.name.clone(),
+                            error: e.to_string(),
+                            diagnostics: self.env.take_failures(),
+                        });
+                        if self.env.keep_going {
+                            progress.println(&format!("   Failed {}", mod_.name));
+                            broken.insert(mod_.name.clone());
+                            continue;
                         }
+                        timings.record("schedule", &round_name, "", round_start, false);
+                        fatal = Some(e);
+                        break 'build;
                     }
-                }
-            }
-            RunStatus::ProjectRoot => {
-                let mods = self.mods.as_mut().unwrap();
-                loop {
-                    let mut next = mods.get_next_buildable()?;
-                    if next.is_empty() {
-                        break;
+                    timings.record("module", &mod_.name, &mod_.name, mod_start, true);
+                    summary.built.push(mod_.name.clone());
+                    if !self.env.dry_run {
+                        let warnings = match self.env.diagnostics.warnings(&mod_.name) {
+                            0 => String::new(),
+                            1 => " (1 warning)".to_string(),
+                            n => format!(" ({} warnings)", n),
+                        };
+                        progress.println(&format!(
+                            "   Built {} in {}{}",
+                            mod_.name,
+                            format_duration(mod_start.elapsed()),
+                            warnings
+                        ));
                     }
-                    for mod_ in &mut next {
-                        mod_.build()?;
-                        if is_run {
-                            mod_.run()?;
+                }
+                if !self.env.dry_run {
+                    mods.write_exports(&mod_.name)?;
+                }
+                let is_cur = cur_name.as_ref().is_none_or(|name| *name == mod_.name);
+                match action {
+                    BuildAction::Build => {}
+                    // dry-run 只输出构建命令，不运行产物
+                    BuildAction::Run if self.env.dry_run => {}
+                    BuildAction::Run => {
+                        if let Err(e) = mod_.run(&self.env) {
+                            summary.failures.push(BuildFailure {
+                                module: mod_.name.clone(),
+                                error: e.to_string(),
+                                diagnostics: Vec::new(),
+                            });
+                            if !self.env.keep_going {
+                                timings.record("schedule", &round_name, "", round_start, false);
+                                fatal = Some(e);
+                                break 'build;
+                            }
                         }
                     }
+                    // 模块目录下只运行当前模块的测试
+                    BuildAction::Test if is_cur => match mod_.test(&self.env) {
+                        Ok(results) => {
+                            for (name, passed) in results {
+                                test_results.push((format!("{}::{}", mod_.name, name), passed));
+                            }
+                        }
+                        // 测试程序编译或链接失败，--keep-going 时记入失败报告并继续
+                        Err(e) => {
+                            summary.failures.push(BuildFailure {
+                                module: mod_.name.clone(),
+                                error: e.to_string(),
+                                diagnostics: self.env.take_failures(),
+                            });
+                            if !self.env.keep_going {
+                                timings.record("schedule", &round_name, "", round_start, false);
+                                fatal = Some(e);
+                                break 'build;
+                            }
+                            progress.println(&format!("   Failed {} tests", mod_.name));
+                        }
+                    },
+                    BuildAction::Test => {}
+                }
+                if cur_name.is_some() && is_cur {
+                    timings.record("schedule", &round_name, "", round_start, true);
+                    break 'build;
                 }
             }
-            RunStatus::Unknown => {
-                return Err("Err project".into());
-            }
+            timings.record("schedule", &round_name, "", round_start, true);
         }
 
+        summary.report(&self.env, start.elapsed());
+        Self::write_timings(&self.env, &self.root)?;
+        if let Some(e) = fatal {
+            return Err(e);
+        }
+        if !summary.failures.is_empty() {
+            summary.report_failures();
+            return Err(format!("{} module(s) failed to build", summary.failures.len()).into());
+        }
+        if action == BuildAction::Test {
+            Self::report_tests(&self.env, &test_results)?;
+        }
+        Ok(())
+    }
+
+    ///写出 --timings 的 Chrome trace 与 HTML 汇总到项目根目录的 build/timings/，
+    ///dry-run 时没有实际执行的步骤，不写文件
+    fn write_timings(env: &BuildEnv, root: &Path) -> Result<(), Box<dyn Error>> {
+        if env.timings.enabled() && env.dry_run {
+            env.progress.println("Timings not written for a dry run");
+        } else if env.timings.enabled() {
+            let html = env.timings.write(&root.join("build").join("timings"))?;
+            env.progress
+                .println(&format!("Timings written to {}", html.display()));
+        }
         Ok(())
     }
 
     ///运行命令
     pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        self.build(true)?;
+        self.build(BuildAction::Run)?;
+        Ok(())
+    }
+
+    ///测试命令：构建后编译并运行 test/ 下的测试程序
+    pub fn test(&mut self) -> Result<(), Box<dyn Error>> {
+        self.build(BuildAction::Test)?;
+        Ok(())
+    }
+
+    ///输出测试汇总，存在失败时返回错误
+    ///json 模式下输出 test-finished 消息，文本汇总改到 stderr
+    fn report_tests(env: &BuildEnv, results: &[(String, bool)]) -> Result<(), Box<dyn Error>> {
+        let failed: Vec<&String> = results
+            .iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(name, _)| name)
+            .collect();
+        let mut lines = vec![format!(
+            "test result: {}. {} passed; {} failed",
+            if failed.is_empty() { "ok" } else { "FAILED" },
+            results.len() - failed.len(),
+            failed.len()
+        )];
+        lines.extend(failed.iter().map(|name| format!("    {}", name)));
+        match env.message_format {
+            MessageFormat::Human => {
+                for line in &lines {
+                    println!("{}", line);
+                }
+            }
+            MessageFormat::Json => {
+                diagnostics::emit_json(&TestFinished {
+                    reason: "test-finished",
+                    success: failed.is_empty(),
+                    passed: results.len() - failed.len(),
+                    failed: failed.iter().map(|name| name.to_string()).collect(),
+                })?;
+                for line in &lines {
+                    env.progress.println(line);
+                }
+            }
+        }
+        if !failed.is_empty() {
+            return Err(format!("{} test(s) failed", failed.len()).into());
+        }
+        Ok(())
+    }
+
+    ///安装命令：构建后将产物安装到 prefix（可由 DESTDIR 暂存），并记录安装清单
+    ///项目根目录下安装所有模块，模块目录下安装当前模块及其依赖
+    pub fn install(&mut self, dirs: &InstallDirs) -> Result<(), Box<dyn Error>> {
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        // 模块目录下安装当前模块及其依赖，使 .pc 与 CMake 文件中引用的依赖都已安装；
+        // 构建会消耗依赖图，需在构建前求出
+        let names: Vec<String> = match self.status {
+            RunStatus::ModRoot => {
+                let wanted = mods.with_deps(&self.cur_mod.as_ref().unwrap().name);
+                mods.project_map
+                    .mod_names
+                    .iter()
+                    .filter(|name| wanted.contains(*name))
+                    .cloned()
+                    .collect()
+            }
+            _ => mods.project_map.mod_names.clone(),
+        };
+
+        self.build(BuildAction::Build)?;
+        let mods = self.mods.as_ref().unwrap();
+        // 同名头文件会互相覆盖，复制任何文件前先检查（含构建生成的头文件）
+        let headers = names
+            .iter()
+            .map(|name| {
+                Ok((
+                    name.clone(),
+                    mods.project_map.indices[name].install_headers(dirs)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        install::check_header_conflicts(&headers)?;
+        let mut installed = Vec::new();
+        for name in &names {
+            let package = mods.package_info(name)?;
+            installed.extend(mods.project_map.indices[name].install(dirs, package.as_ref())?);
+        }
+        let manifest = InstallManifest::write(&self.root, &installed)?;
+        println!(
+            "Installed {} file(s) to {}, manifest: {}",
+            installed.len(),
+            dirs.stage.display(),
+            manifest.display()
+        );
+        Ok(())
+    }
+
+    ///卸载命令：按安装清单删除已安装的文件
+    pub fn uninstall(&mut self) -> Result<(), Box<dyn Error>> {
+        let removed = InstallManifest::uninstall(&self.root)?;
+        println!("Removed {} file(s)", removed);
+        Ok(())
+    }
+
+    ///打包命令：将模块打包为 .tar.gz 归档，项目根目录下打包所有项目模块（不含外部依赖），
+    ///模块目录下只打包当前模块；prebuilt 时先构建并附带产物
+    pub fn package(&mut self, prebuilt: bool, output: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        if prebuilt {
+            self.build(BuildAction::Build)?;
+        }
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let names: Vec<String> = match self.status {
+            RunStatus::ModRoot => vec![self.cur_mod.as_ref().unwrap().name.clone()],
+            _ => mods
+                .project_map
+                .mod_names
+                .iter()
+                .filter(|n| !mods.externals.iter().any(|e| &e.spec.name == *n))
+                .cloned()
+                .collect(),
+        };
+
+        for name in &names {
+            let modfile = &mods.project_map.indices[name];
+            let out_dir = match output {
+                Some(dir) => dir.to_path_buf(),
+                None => modfile.absolute_path.join("build").join("package"),
+            };
+            let archive = modfile.package(&self.env, &out_dir, prebuilt)?;
+            println!("Packaged {}: {}", name, archive.display());
+        }
+        Ok(())
+    }
+
+    ///覆盖率命令：以 --coverage 插桩构建并运行测试，输出各模块的行与分支覆盖率，
+    ///写出 lcov 与 HTML 报告；低于配置或命令行给出的最低覆盖率时返回错误
+    pub fn coverage(
+        &mut self,
+        fail_under_lines: Option<f64>,
+        fail_under_branches: Option<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let names: Vec<String> = match self.status {
+            RunStatus::ModRoot => vec![self.cur_mod.as_ref().unwrap().name.clone()],
+            _ => mods
+                .project_map
+                .mod_names
+                .iter()
+                .filter(|n| !mods.externals.iter().any(|e| &e.spec.name == *n))
+                .cloned()
+                .collect(),
+        };
+        if !self.env.dry_run {
+            for name in &names {
+                mods.project_map.indices[name].clear_coverage_data()?;
+            }
+        }
+
+        self.build(BuildAction::Test)?;
+        if self.env.dry_run {
+            return Ok(());
+        }
+
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let mut report = Vec::new();
+        for name in &names {
+            let modfile = &mods.project_map.indices[name];
+            let module = modfile.coverage(&self.env)?;
+            if !module.files.is_empty() {
+                report.push(module);
+            }
+        }
+        if report.is_empty() {
+            return Err("No coverage data: no module has C sources".into());
+        }
+
+        coverage::print_text(&report);
+        let dir = self.root.join("build").join("coverage-report");
+        let lcov = dir.join("lcov.info");
+        coverage::write_lcov(&report, &lcov)?;
+        let html = coverage::write_html(&report, &dir.join("html"))?;
+        println!("lcov: {}", lcov.display());
+        println!("HTML: {}", html.display());
+
+        // 各模块配置的最低覆盖率，以及命令行给出的整体最低覆盖率
+        let mut below = Vec::new();
+        let mut check = |what: String, actual: f64, min: Option<f64>| {
+            if let Some(min) = min.filter(|min| actual < *min) {
+                below.push(format!("{} {:.1}% < {}%", what, actual, min));
+            }
+        };
+        for module in &report {
+            let totals = module.totals();
+            let threshold = module.threshold.clone().unwrap_or_default();
+            check(
+                format!("{} lines", module.name),
+                totals.line_percent(),
+                threshold.lines,
+            );
+            check(
+                format!("{} branches", module.name),
+                totals.branch_percent(),
+                threshold.branches,
+            );
+        }
+        let totals = coverage::project_totals(&report);
+        check(
+            "total lines".to_string(),
+            totals.line_percent(),
+            fail_under_lines,
+        );
+        check(
+            "total branches".to_string(),
+            totals.branch_percent(),
+            fail_under_branches,
+        );
+        if !below.is_empty() {
+            return Err(format!("Coverage below threshold: {}", below.join(", ")).into());
+        }
+        Ok(())
+    }
+
+    ///静态分析命令：以与编译相同的 include 路径与宏定义并行运行 clang-tidy / cppcheck，
+    ///按模块汇总结果；changed 给出时只分析相对该提交有改动的文件
+    pub fn lint(
+        &mut self,
+        tools: &[LintTool],
+        changed: Option<&str>,
+        jobs: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let tools: Vec<LintTool> = if tools.is_empty() {
+            let found: Vec<LintTool> = LintTool::ALL
+                .into_iter()
+                .filter(|t| find_program(t.program()).is_some())
+                .collect();
+            if found.is_empty() {
+                return Err("Neither clang-tidy nor cppcheck was found in PATH".into());
+            }
+            found
+        } else {
+            for tool in tools {
+                if find_program(tool.program()).is_none() {
+                    return Err(format!("{} was not found in PATH", tool.program()).into());
+                }
+            }
+            tools.to_vec()
+        };
+
+        let changed: Option<HashSet<PathBuf>> = match changed {
+            Some(base) => Some(
+                git::changed_files(&self.root, base)?
+                    .iter()
+                    .filter_map(|p| fs::canonicalize(p).ok())
+                    .collect(),
+            ),
+            None => None,
+        };
+
+        let mods = self.mods.as_mut().ok_or("Err project")?;
+        // 生成版本头文件并解析 pkg-config，使分析时的头文件与编译参数齐全
+        mods.configure(&self.env)?;
+        let names: Vec<String> = match self.status {
+            RunStatus::ModRoot => vec![self.cur_mod.as_ref().unwrap().name.clone()],
+            _ => mods
+                .project_map
+                .mod_names
+                .iter()
+                .filter(|n| !mods.externals.iter().any(|e| &e.spec.name == *n))
+                .cloned()
+                .collect(),
+        };
+
+        let mut queue = Vec::new();
+        for name in &names {
+            let modfile = &mods.project_map.indices[name];
+            for source in modfile.lint_sources() {
+                if changed.as_ref().is_some_and(|set| !set.contains(&source)) {
+                    continue;
+                }
+                for &tool in &tools {
+                    let what = format!("{} ({})", modfile.display(&source), tool.program());
+                    queue.push((what, modfile.lint_cmd(&self.env, tool, &source)?));
+                }
+            }
+        }
+        if queue.is_empty() {
+            println!("No files to lint");
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let total = queue.len();
+        self.env.progress.start(total);
+        let queue = Mutex::new(queue);
+        let failed = Mutex::new(Vec::new());
+        let env = &self.env;
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.max(1).min(total) {
+                scope.spawn(|| loop {
+                    let Some((what, mut cmd)) = queue.lock().unwrap().pop() else {
+                        break;
+                    };
+                    match env.exec("Linting", &what, &mut cmd) {
+                        Ok(true) => {}
+                        Ok(false) => failed.lock().unwrap().push(what),
+                        Err(e) => failed.lock().unwrap().push(format!("{}: {}", what, e)),
+                    }
+                });
+            }
+        });
+        self.env.take_failures();
+        let failed = failed.into_inner().unwrap();
+
+        let warnings = self.env.diagnostics.warning_counts();
+        let errors = self.env.diagnostics.error_counts();
+        let count = |map: &BTreeMap<String, usize>, name: &str| map.get(name).copied().unwrap_or(0);
+        for name in &names {
+            let (w, e) = (count(&warnings, name), count(&errors, name));
+            if w + e > 0 {
+                self.env
+                    .progress
+                    .println(&format!("   {}: {} warning(s), {} error(s)", name, w, e));
+            }
+        }
+        let warning_total: usize = warnings.values().sum();
+        let error_total: usize = errors.values().sum();
+        if self.env.message_format == MessageFormat::Json {
+            diagnostics::emit_json(&LintFinished {
+                reason: "lint-finished",
+                success: failed.is_empty() && error_total == 0,
+                checks: total,
+                warnings: warnings.clone(),
+                errors: errors.clone(),
+            })?;
+        }
+        self.env.progress.println(&format!(
+            "Finished lint: {} check(s), {} warning(s), {} error(s) in {}",
+            total,
+            warning_total,
+            error_total,
+            format_duration(start.elapsed())
+        ));
+
+        for what in &failed {
+            eprintln!("  failed: {}", what);
+        }
+        if !failed.is_empty() || error_total > 0 {
+            return Err(format!(
+                "Lint failed: {} error(s), {} check(s) failed",
+                error_total,
+                failed.len()
+            )
+            .into());
+        }
+        if self.env.deny_warnings && warning_total > 0 {
+            return Err(format!("Lint failed: {} warning(s) denied", warning_total).into());
+        }
+        Ok(())
+    }
+
+    ///元数据命令：以 JSON 或 YAML 输出项目中所有模块的元数据
+    pub fn metadata(&mut self, format: &str) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        self.probe_compilers()?;
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let metadata = mods.metadata(&self.env, &self.root)?;
+        let text = match format {
+            "json" => serde_json::to_string_pretty(&metadata)?,
+            "yaml" => serde_yaml::to_string(&metadata)?,
+            other => {
+                return Err(
+                    format!("Unknown metadata format: {} (expected json or yaml)", other).into(),
+                )
+            }
+        };
+        println!("{}", text);
+        Ok(())
+    }
+
+    ///vendor 命令：将外部依赖复制到项目 vendor/ 目录，之后的构建不再访问远端仓库
+    pub fn vendor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        let mods = self.mods.as_ref().ok_or("Err project")?;
+        let vendored = mods.vendor(&self.root)?;
+        if vendored.is_empty() {
+            println!("No external modules to vendor");
+        } else {
+            println!(
+                "Vendored {} external module(s) into vendor/: {}",
+                vendored.len(),
+                vendored.join(", ")
+            );
+        }
         Ok(())
     }
 
@@ -131,9 +1006,114 @@ impl CmdCtx {
         Ok(())
     }
 
-    ///生成新项目mod目录
-    pub fn gen(&self, name: &str) -> Result<(), Box<dyn Error>> {
-        ModFile::gen(name)?;
+    ///生成新项目mod目录：使用内置模板，或给定的用户模板目录
+    pub fn gen(
+        &self,
+        name: &str,
+        kind: TemplateKind,
+        user_template: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        match user_template {
+            Some(t) => template::generate_from(name, &template::find_template(t)?),
+            None => template::generate(name, kind),
+        }
+    }
+
+    ///add 命令：在当前模块的 config.yaml 中追加对项目内模块的依赖，保留原有注释与格式
+    pub fn add(&mut self, target: &str, version: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        let (Some(cur), Some(mods)) = (&self.cur_mod, &self.mods) else {
+            return Err("hk add must be run in a module directory".into());
+        };
+        mods.check_new_dep(&cur.name, target)?;
+
+        let dep = &mods.project_map.indices[target];
+        if let Some(req) = version {
+            let parsed = semver::VersionReq::parse(req)
+                .map_err(|e| format!("Invalid version requirement '{}': {}", req, e))?;
+            let dep_version = &dep.config()?.version;
+            if !parsed.matches(&semver::Version::parse(dep_version)?) {
+                return Err(format!(
+                    "Module '{}' has version {}, which does not satisfy {}",
+                    target, dep_version, req
+                )
+                .into());
+            }
+        }
+
+        // mod_deps 中的路径相对于当前模块目录，如 ../net
+        let path = relative_path(&cur.absolute_path, &dep.absolute_path);
+
+        let config_path = cur.absolute_path.join("config.yaml");
+        let text = std::fs::read_to_string(&config_path)?;
+        let new_text = yaml_edit::add_mod_dep(&text, &path, version)?;
+        let mut expected = cur.config()?.dep.mod_deps.clone();
+        expected.push(ModDep {
+            path: path.clone(),
+            version: version.map(str::to_string),
+        });
+        Self::write_mod_deps(&config_path, &new_text, &expected)?;
+        println!("Added {} to {}", path, cur.name);
+        Ok(())
+    }
+
+    ///remove 命令：从当前模块的 config.yaml 中删除对某个项目内模块的依赖
+    pub fn remove(&mut self, target: &str) -> Result<(), Box<dyn Error>> {
+        self.detect_layout()?;
+        let Some(cur) = &self.cur_mod else {
+            return Err("hk remove must be run in a module directory".into());
+        };
+
+        let config_path = cur.absolute_path.join("config.yaml");
+        let text = std::fs::read_to_string(&config_path)?;
+        let (new_text, found) = yaml_edit::remove_mod_dep(&text, target)?;
+        if !found {
+            return Err(format!("Module '{}' does not depend on '{}'", cur.name, target).into());
+        }
+        let mut expected = cur.config()?.dep.mod_deps.clone();
+        expected.retain(|d| d.name() != target);
+        Self::write_mod_deps(&config_path, &new_text, &expected)?;
+        println!("Removed {} from {}", target, cur.name);
+        Ok(())
+    }
+
+    ///校验编辑后的配置解析出的 mod_deps 与预期一致后再写回，避免写坏 config.yaml
+    fn write_mod_deps(path: &Path, text: &str, expected: &[ModDep]) -> Result<(), Box<dyn Error>> {
+        let config: Config = serde_yaml::from_str(text).map_err(|e| {
+            format!(
+                "Edited {} is invalid ({}), left unchanged",
+                path.display(),
+                e
+            )
+        })?;
+        if config.dep.mod_deps != expected {
+            return Err(format!(
+                "Could not edit mod_deps in {} automatically, edit it by hand",
+                path.display()
+            )
+            .into());
+        }
+        std::fs::write(path, text)?;
         Ok(())
     }
+
+    ///将当前目录已有的 C 源码转换为模块
+    pub fn init(&self, name: Option<&str>) -> Result<(), Box<dyn Error>> {
+        template::init(Path::new("."), name)
+    }
+}
+
+/// 从目录 from 到 to 的相对路径（两者均为绝对路径）
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for c in &to[common..] {
+        path.push(c);
+    }
+    path.to_string_lossy().replace('\\', "/")
 }
@@ -3,12 +3,19 @@ mod module;
 mod utils;
 
 use crate::{
-    cli::context::CmdCtx,
-    utils::yaml,
-    utils::log,
+    cli::context::{BuildAction, CmdCtx},
+    module::{
+        env::{BuildEnv, Profile},
+        install::InstallDirs,
+        lint::LintTool,
+        template::TemplateKind,
+    },
+    utils::diagnostics::MessageFormat,
     utils::logo::print_logo,
+    utils::toolchain::Toolchain,
+    utils::yaml::Sanitizer,
 };
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
 use std::{error::Error, path::PathBuf};
 #[derive(Parser)]
 #[command(
@@ -20,70 +27,483 @@ use std::{error::Error, path::PathBuf};
 struct Cli {
     #[command(subcommand)]
     cmd: Option<Command>,
+
+    #[arg(
+        short,
+        action = ArgAction::Count,
+        global = true,
+        help = "Show more log output (-v info, -vv debug, -vvv trace); also HK_LOG=debug or HK_LOG=info,git=debug"
+    )]
+    v: u8,
+
+    #[arg(short, long, global = true, conflicts_with = "v", help = "Only show errors")]
+    quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..=1,
+        default_missing_value = "build/hk.log",
+        value_name = "PATH",
+        help = "Write the full log with all levels to a file (default: build/hk.log)"
+    )]
+    log_file: Option<PathBuf>,
+}
+/// 所有读取项目配置的命令共用的参数
+#[derive(Debug, Args)]
+struct CommonArgs {
+    #[arg(
+        short,
+        long,
+        help = "Build mode: debug or release (default: debug)",
+        default_value = "debug"
+    )]
+    mode: String,
+
+    #[arg(
+        long,
+        help = "Toolchain name (toolchains/<name>.yaml) or path applied to every module"
+    )]
+    toolchain: Option<String>,
+
+    #[arg(
+        long,
+        help = "Never clone or fetch external modules; use vendored copies and existing checkouts"
+    )]
+    offline: bool,
+
+    #[arg(long, help = "Print every command shell-quoted before running it")]
+    verbose: bool,
+}
+
+impl CommonArgs {
+    fn env(&self) -> Result<BuildEnv, Box<dyn Error>> {
+        let mut env = BuildEnv::new(Profile::parse(&self.mode)?);
+        env.offline = self.offline;
+        env.verbose = self.verbose;
+        if let Some(name) = &self.toolchain {
+            let toolchain = Toolchain::find(name)?;
+            logi!("Using toolchain: {}", toolchain.name);
+            env.toolchain = Some(toolchain);
+        }
+        Ok(env)
+    }
+}
+
+/// 编译诊断输出格式，json 时其余输出改到 stderr
+fn set_message_format(env: &mut BuildEnv, format: &str) -> Result<(), Box<dyn Error>> {
+    env.message_format = MessageFormat::parse(format)?;
+    if env.message_format == MessageFormat::Json {
+        env.progress.use_stderr();
+    }
+    Ok(())
+}
+
+/// 构建模块的命令（build / run / test / install / package / coverage）共用的参数
+#[derive(Debug, Args)]
+struct BuildArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[arg(
+        long,
+        help = "Print all compile, archive and link commands in order without running them"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Record every step and module and write build/timings/hk-timings.{json,html}"
+    )]
+    timings: bool,
+
+    #[arg(
+        long,
+        help = "Keep building modules whose dependencies succeeded after a failure, then report all failures"
+    )]
+    keep_going: bool,
+
+    #[arg(
+        long,
+        help = "Compiler message format: human or json (one JSON object per line on stdout)",
+        default_value = "human"
+    )]
+    message_format: String,
+
+    #[arg(
+        long,
+        help = "Treat warnings as errors in every module, overriding their warnings policy"
+    )]
+    deny_warnings: bool,
+}
+
+impl BuildArgs {
+    fn env(&self) -> Result<BuildEnv, Box<dyn Error>> {
+        let mut env = self.common.env()?;
+        env.dry_run = self.dry_run;
+        env.keep_going = self.keep_going;
+        env.deny_warnings = self.deny_warnings;
+        set_message_format(&mut env, &self.message_format)?;
+        if self.timings {
+            env.timings.enable();
+        }
+        Ok(env)
+    }
+}
+
+/// 可启用 sanitizer 的命令（build / run / test / coverage）的参数
+#[derive(Debug, Args)]
+struct SanitizeArgs {
+    #[arg(
+        long,
+        value_name = "LIST",
+        help = "Build every module with sanitizers, e.g. address,undefined (output under build/sanitize-*)"
+    )]
+    sanitize: Option<String>,
+}
+
+impl SanitizeArgs {
+    fn apply(&self, env: &mut BuildEnv) -> Result<(), Box<dyn Error>> {
+        if let Some(list) = &self.sanitize {
+            env.sanitize = Sanitizer::parse_list(list)?;
+        }
+        Ok(())
+    }
 }
+
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Build the current module or entire project
     Build {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[command(flatten)]
+        sanitize: SanitizeArgs,
+    },
+
+    /// Remove all build artifacts (build/ directory)
+    Clean,
+
+    /// Build and run the module or project
+    Run {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[command(flatten)]
+        sanitize: SanitizeArgs,
+    },
+
+    /// Build the module or project and run the programs under test/
+    Test {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[command(flatten)]
+        sanitize: SanitizeArgs,
+    },
+
+    /// Probe compilers and tools used by the project and report problems
+    Doctor {
+        #[command(flatten)]
+        args: CommonArgs,
+    },
+
+    /// Build and install executables, libraries, headers and pkg-config files
+    Install {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[arg(long, help = "Installation prefix", default_value = "/usr/local")]
+        prefix: PathBuf,
+
+        #[arg(
+            long,
+            help = "Staging directory prepended to the prefix (default: $DESTDIR)"
+        )]
+        destdir: Option<PathBuf>,
+    },
+
+    /// Remove files recorded by the last `hk install`
+    Uninstall,
+
+    /// Package modules into versioned .tar.gz archives with a manifest and checksum
+    Package {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[arg(long, help = "Build first and include the built library and executables")]
+        prebuilt: bool,
+
+        #[arg(long, help = "Output directory (default: <module>/build/package)")]
+        output: Option<PathBuf>,
+    },
+
+    /// Build with coverage instrumentation, run the tests and report line and branch coverage
+    Coverage {
+        #[command(flatten)]
+        args: BuildArgs,
+
+        #[command(flatten)]
+        sanitize: SanitizeArgs,
+
+        #[arg(
+            long,
+            value_name = "PCT",
+            help = "Fail if total line coverage is below PCT percent"
+        )]
+        fail_under_lines: Option<f64>,
+
+        #[arg(
+            long,
+            value_name = "PCT",
+            help = "Fail if total branch coverage is below PCT percent"
+        )]
+        fail_under_branches: Option<f64>,
+    },
+
+    /// Run clang-tidy and/or cppcheck over module sources with the compile include paths and defines
+    Lint {
+        #[command(flatten)]
+        args: CommonArgs,
+
+        #[arg(
+            long,
+            help = "Diagnostic message format: human or json (one JSON object per line on stdout)",
+            default_value = "human"
+        )]
+        message_format: String,
+
+        #[arg(long, help = "Fail when any analyzer reports a warning")]
+        deny_warnings: bool,
+
+        #[arg(
+            long,
+            value_name = "TOOL",
+            help = "Analyzer to run: clang-tidy or cppcheck (repeatable, default: all found in PATH)"
+        )]
+        tool: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "BASE",
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            help = "Only analyze files changed relative to BASE (default: HEAD), including untracked files"
+        )]
+        changed: Option<String>,
+
         #[arg(
             short,
             long,
-            help = "Build mode: debug or release (default: debug)",
-            default_value = "debug"
+            help = "Number of files analyzed in parallel (default: available CPUs)"
         )]
-        mode: Option<String>,
+        jobs: Option<usize>,
     },
 
-    /// Remove all build artifacts (build/ directory)
-    Clean,
+    /// Print machine-readable metadata about all modules in the project
+    Metadata {
+        #[command(flatten)]
+        args: CommonArgs,
 
-    /// Build and run the module or project
-    Run,
+        #[arg(long, help = "Output format: json or yaml", default_value = "json")]
+        format: String,
+    },
+
+    /// Copy all external modules into vendor/ so builds work without network access
+    Vendor {
+        #[command(flatten)]
+        args: CommonArgs,
+    },
 
     /// Create a new module with the standard directory structure
     New {
-        #[arg(help = "Name of the new module to create", default_value = "new_hk_project")]
+        #[arg(
+            help = "Name of the new module to create",
+            default_value = "new_hk_project"
+        )]
+        name: Option<String>,
+
+        #[arg(long, help = "Executable module with a hello world bin/main.c (default)")]
+        bin: bool,
+
+        #[arg(long, conflicts_with = "bin", help = "Library module with a header, source and test")]
+        lib: bool,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["bin", "lib"],
+            help = "Project with a library module core and an executable module app"
+        )]
+        project: bool,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["bin", "lib", "project"],
+            help = "User template directory, or name under templates/ or ~/.hk/templates/"
+        )]
+        template: Option<String>,
+    },
+
+    /// Add a project module to the current module's mod_deps
+    Add {
+        #[arg(help = "Name of the module to depend on")]
+        module: String,
+
+        #[arg(long, help = "Version requirement, e.g. \"^1.2\"")]
+        version: Option<String>,
+    },
+
+    /// Remove a module from the current module's mod_deps
+    Remove {
+        #[arg(help = "Name of the module to remove")]
+        module: String,
+    },
+
+    /// Turn the C sources in the current directory into a module
+    Init {
+        #[arg(long, help = "Module name (default: directory name)")]
         name: Option<String>,
     },
 }
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
+    utils::log::init(cli.v, cli.quiet, cli.log_file.as_deref())?;
     let mut cmd_data = CmdCtx::new();
     // println!("build mode is {:#?}",cmd_data);
     match &cli.cmd {
-        Some(Command::New { name }) => {
+        Some(Command::New {
+            name,
+            bin: _,
+            lib,
+            project,
+            template,
+        }) => {
+            let kind = if *lib {
+                TemplateKind::Lib
+            } else if *project {
+                TemplateKind::Project
+            } else {
+                TemplateKind::Bin
+            };
             if let Some(name_vale) = name {
-                cmd_data.gen(name_vale)?;
+                cmd_data.gen(name_vale, kind, template.as_deref())?;
             } else {
                 println!("have no name");
             }
         }
-        Some(Command::Build { mode }) => {
+        Some(Command::Add { module, version }) => {
+            cmd_data.add(module, version.as_deref())?;
+        }
+        Some(Command::Remove { module }) => {
+            cmd_data.remove(module)?;
+        }
+        Some(Command::Init { name }) => {
+            cmd_data.init(name.as_deref())?;
+        }
+        Some(Command::Build { args, sanitize }) => {
+            let mut env = args.env()?;
+            sanitize.apply(&mut env)?;
+            env.config_sanitize = true;
+            cmd_data.set_env(env);
             cmd_data.detect_env()?;
-            match mode {
-                Some(value) => {
-                    if value.eq_ignore_ascii_case("release") {
-                        println!("Release Mode");
-                    } else {
-                        cmd_data.build(false)?;
-                        println!("Debug Mode");
-                    }
-                }
-                _ => {
-                    println!("Debug Mode");
-                }
-            }
+            cmd_data.build(BuildAction::Build)?;
         }
         Some(Command::Clean) => {
-            cmd_data.detect_env()?;
+            cmd_data.detect_layout()?;
             print_logo();
             cmd_data.clean()?;
         }
 
-        Some(Command::Run) => {
+        Some(Command::Run { args, sanitize }) => {
+            let mut env = args.env()?;
+            sanitize.apply(&mut env)?;
+            env.config_sanitize = true;
+            cmd_data.set_env(env);
             cmd_data.detect_env()?;
             cmd_data.run()?;
         }
+        Some(Command::Test { args, sanitize }) => {
+            let mut env = args.env()?;
+            sanitize.apply(&mut env)?;
+            env.config_sanitize = true;
+            cmd_data.set_env(env);
+            cmd_data.detect_env()?;
+            cmd_data.test()?;
+        }
+        Some(Command::Doctor { args }) => {
+            cmd_data.set_env(args.env()?);
+            cmd_data.doctor()?;
+        }
+        Some(Command::Coverage {
+            args,
+            sanitize,
+            fail_under_lines,
+            fail_under_branches,
+        }) => {
+            let mut env = args.env()?;
+            sanitize.apply(&mut env)?;
+            env.coverage = true;
+            cmd_data.set_env(env);
+            cmd_data.detect_env()?;
+            cmd_data.coverage(*fail_under_lines, *fail_under_branches)?;
+        }
+        Some(Command::Lint {
+            args,
+            message_format,
+            deny_warnings,
+            tool,
+            changed,
+            jobs,
+        }) => {
+            let tools = tool
+                .iter()
+                .map(|t| LintTool::parse(t))
+                .collect::<Result<Vec<_>, _>>()?;
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |n| n.get())
+            });
+            let mut env = args.env()?;
+            set_message_format(&mut env, message_format)?;
+            env.deny_warnings = *deny_warnings;
+            cmd_data.set_env(env);
+            cmd_data.detect_env()?;
+            cmd_data.lint(&tools, changed.as_deref(), jobs)?;
+        }
+        Some(Command::Install {
+            args,
+            prefix,
+            destdir,
+        }) => {
+            let destdir = destdir
+                .clone()
+                .or_else(|| std::env::var_os("DESTDIR").map(PathBuf::from));
+            let dirs = InstallDirs::new(prefix, destdir.as_deref())?;
+            cmd_data.set_env(args.env()?);
+            cmd_data.detect_env()?;
+            cmd_data.install(&dirs)?;
+        }
+        Some(Command::Uninstall) => {
+            cmd_data.detect_layout()?;
+            cmd_data.uninstall()?;
+        }
+        Some(Command::Package {
+            args,
+            prebuilt,
+            output,
+        }) => {
+            cmd_data.set_env(args.env()?);
+            cmd_data.detect_env()?;
+            cmd_data.package(*prebuilt, output.as_deref())?;
+        }
+        Some(Command::Metadata { args, format }) => {
+            cmd_data.set_env(args.env()?);
+            cmd_data.metadata(format)?;
+        }
+        Some(Command::Vendor { args }) => {
+            cmd_data.set_env(args.env()?);
+            cmd_data.vendor()?;
+        }
         None => {
             // 显示帮助信息
             print_logo();
@@ -0,0 +1,459 @@
+use crate::logd;
+use crate::module::env::BuildEnv;
+use crate::module::mod_file::ModFile;
+use crate::utils::timings::escape;
+use crate::utils::yaml::CoverageThreshold;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 一个源文件的覆盖率：每行执行次数与每行各分支的执行次数（None 表示所在块未执行）
+#[derive(Debug, Clone, Default)]
+pub struct FileCoverage {
+    pub path: PathBuf,
+    pub lines: BTreeMap<u32, u64>,
+    pub branches: BTreeMap<u32, Vec<Option<u64>>>,
+}
+
+/// 行与分支的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Totals {
+    pub lines_found: usize,
+    pub lines_hit: usize,
+    pub branches_found: usize,
+    pub branches_hit: usize,
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.lines_found += other.lines_found;
+        self.lines_hit += other.lines_hit;
+        self.branches_found += other.branches_found;
+        self.branches_hit += other.branches_hit;
+    }
+
+    /// 行覆盖率百分比，没有可执行行时为 100
+    pub fn line_percent(&self) -> f64 {
+        percent(self.lines_hit, self.lines_found)
+    }
+
+    pub fn branch_percent(&self) -> f64 {
+        percent(self.branches_hit, self.branches_found)
+    }
+}
+
+fn percent(hit: usize, found: usize) -> f64 {
+    if found == 0 {
+        100.0
+    } else {
+        hit as f64 * 100.0 / found as f64
+    }
+}
+
+impl FileCoverage {
+    pub fn totals(&self) -> Totals {
+        let branches = self.branches.values().flatten();
+        Totals {
+            lines_found: self.lines.len(),
+            lines_hit: self.lines.values().filter(|&&n| n > 0).count(),
+            branches_found: branches.clone().count(),
+            branches_hit: branches.filter(|b| b.is_some_and(|n| n > 0)).count(),
+        }
+    }
+
+    /// 合并同一文件在另一个翻译单元中的数据（如头文件）
+    fn merge(&mut self, other: FileCoverage) {
+        for (line, count) in other.lines {
+            *self.lines.entry(line).or_default() += count;
+        }
+        for (line, list) in other.branches {
+            let merged = self.branches.entry(line).or_default();
+            if merged.len() < list.len() {
+                merged.resize(list.len(), None);
+            }
+            for (slot, taken) in merged.iter_mut().zip(list) {
+                *slot = match (*slot, taken) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+    }
+}
+
+/// 一个模块的覆盖率
+#[derive(Debug, Clone)]
+pub struct ModuleCoverage {
+    pub name: String,
+    pub root: PathBuf,
+    pub files: Vec<FileCoverage>,
+    /// 配置中的最低覆盖率
+    pub threshold: Option<CoverageThreshold>,
+}
+
+impl ModuleCoverage {
+    pub fn totals(&self) -> Totals {
+        let mut totals = Totals::default();
+        for file in &self.files {
+            totals.add(file.totals());
+        }
+        totals
+    }
+
+    /// 相对模块根目录的显示路径
+    fn display(&self, file: &FileCoverage) -> String {
+        file.path
+            .strip_prefix(&self.root)
+            .unwrap_or(&file.path)
+            .display()
+            .to_string()
+    }
+}
+
+/// 所有模块的合计
+pub fn project_totals(mods: &[ModuleCoverage]) -> Totals {
+    let mut totals = Totals::default();
+    for m in mods {
+        totals.add(m.totals());
+    }
+    totals
+}
+
+/// 解析一个 .gcov 文件（gcov -b -c 或 llvm-cov gcov -b -c 的输出）
+fn parse_gcov(text: &str) -> Option<FileCoverage> {
+    let mut file = FileCoverage::default();
+    let mut current = 0;
+    for line in text.lines() {
+        if let Some(rest) = line.trim_start().strip_prefix("branch") {
+            // branch  0 taken 5 (fallthrough) / branch  1 never executed
+            let taken = rest
+                .split_whitespace()
+                .skip_while(|w| *w != "taken")
+                .nth(1)
+                .and_then(|n| n.parse::<u64>().ok());
+            file.branches.entry(current).or_default().push(taken);
+            continue;
+        }
+        let mut parts = line.splitn(3, ':');
+        let (Some(count), Some(number), Some(source)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let Ok(number) = number.trim().parse::<u32>() else {
+            continue;
+        };
+        if number == 0 {
+            if let Some(path) = source.strip_prefix("Source:") {
+                file.path = PathBuf::from(path);
+            }
+            continue;
+        }
+        current = number;
+        let count = count.trim().trim_end_matches('*');
+        match count {
+            "-" => {}
+            "#####" | "=====" => {
+                file.lines.insert(number, 0);
+            }
+            n => {
+                if let Ok(n) = n.parse::<u64>() {
+                    file.lines.insert(number, n);
+                }
+            }
+        }
+    }
+    (!file.path.as_os_str().is_empty()).then_some(file)
+}
+
+impl ModFile {
+    /// 删除上次运行留下的 .gcda 计数文件，使覆盖率只反映本次测试
+    pub fn clear_coverage_data(&self) -> Result<(), Box<dyn Error>> {
+        fn walk(dir: &Path) -> Result<(), Box<dyn Error>> {
+            let Ok(entries) = fs::read_dir(dir) else {
+                return Ok(());
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if path.is_dir() {
+                    walk(&path)?;
+                } else if path.extension().is_some_and(|ext| ext == "gcda") {
+                    fs::remove_file(&path)?;
+                }
+            }
+            Ok(())
+        }
+        walk(&self.build_dir.join("object"))
+    }
+
+    /// 对 src/ 与 bin/ 下的源文件运行 gcov（clang 使用 llvm-cov gcov），
+    /// 汇总模块目录内（不含 test/）各文件的行与分支覆盖率
+    pub fn coverage(&self, env: &BuildEnv) -> Result<ModuleCoverage, Box<dyn Error>> {
+        let config = self.config()?;
+        let clang = env.is_clang(&config.compiler);
+        let mut tool = Command::new(config.compiler.gcov_tool(clang));
+        if clang {
+            tool.arg("gcov");
+        }
+        let work = self.build_dir.join("gcov");
+        fs::create_dir_all(&work)?;
+
+        let object_dir = self.build_dir.join("object");
+        let sources = [
+            (&self.lib_sources, object_dir.join("src")),
+            (&self.bin_sources, object_dir.join("bin")),
+        ];
+        let mut files: BTreeMap<PathBuf, FileCoverage> = BTreeMap::new();
+        for (list, dir) in sources {
+            for source in list.iter().flatten() {
+                if source.extension().is_none_or(|ext| ext != "c") {
+                    continue;
+                }
+                let obj = Self::object_path(&dir, source)?;
+                for file in self.run_gcov(&tool, &work, source, &obj)? {
+                    match files.get_mut(&file.path) {
+                        Some(existing) => existing.merge(file),
+                        None => {
+                            files.insert(file.path.clone(), file);
+                        }
+                    }
+                }
+            }
+        }
+        fs::remove_dir_all(&work)?;
+
+        let test_dir = self.absolute_path.join("test");
+        let build_dir = self.absolute_path.join("build");
+        Ok(ModuleCoverage {
+            name: self.name.clone(),
+            root: self.absolute_path.clone(),
+            files: files
+                .into_values()
+                .filter(|f| {
+                    f.path.starts_with(&self.absolute_path)
+                        && !f.path.starts_with(&test_dir)
+                        && !f.path.starts_with(&build_dir)
+                })
+                .collect(),
+            threshold: config.coverage.clone(),
+        })
+    }
+
+    /// 对单个源文件运行 gcov，读取并删除生成的 .gcov 文件
+    fn run_gcov(
+        &self,
+        tool: &Command,
+        work: &Path,
+        source: &Path,
+        obj: &Path,
+    ) -> Result<Vec<FileCoverage>, Box<dyn Error>> {
+        let mut cmd = Command::new(tool.get_program());
+        cmd.args(tool.get_args())
+            .args(["-b", "-c", "-o"])
+            .arg(obj)
+            .arg(source)
+            .current_dir(work);
+        let output = cmd
+            .output()
+            .map_err(|e| format!("Failed to run {:?}: {}", cmd.get_program(), e))?;
+        logd!(
+            "gcov {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        if !output.status.success() {
+            return Err(format!(
+                "{:?} failed for {}: {}",
+                cmd.get_program(),
+                source.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(work)?.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "gcov") {
+                if let Some(mut file) = parse_gcov(&fs::read_to_string(&path)?) {
+                    if file.path.is_relative() {
+                        file.path = self.absolute_path.join(&file.path);
+                    }
+                    files.push(file);
+                }
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(files)
+    }
+}
+
+fn summary(totals: Totals) -> (String, String) {
+    (
+        format!(
+            "{:6.1}% ({}/{})",
+            totals.line_percent(),
+            totals.lines_hit,
+            totals.lines_found
+        ),
+        format!(
+            "{:6.1}% ({}/{})",
+            totals.branch_percent(),
+            totals.branches_hit,
+            totals.branches_found
+        ),
+    )
+}
+
+/// 文本报告：每个模块及其文件的行与分支覆盖率
+pub fn print_text(mods: &[ModuleCoverage]) {
+    let width = mods
+        .iter()
+        .flat_map(|m| {
+            std::iter::once(m.name.len()).chain(m.files.iter().map(|f| m.display(f).len() + 2))
+        })
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    println!("{:<width$}  {:<22}  branches", "", "lines");
+    for m in mods {
+        let (lines, branches) = summary(m.totals());
+        println!("{:<width$}  {:<22}  {}", m.name, lines, branches);
+        for file in &m.files {
+            let (lines, branches) = summary(file.totals());
+            println!(
+                "{:<width$}  {:<22}  {}",
+                format!("  {}", m.display(file)),
+                lines,
+                branches
+            );
+        }
+    }
+    let (lines, branches) = summary(project_totals(mods));
+    println!("{:<width$}  {:<22}  {}", "total", lines, branches);
+}
+
+/// 写出 lcov tracefile（genhtml、Codecov 等工具可直接读取）
+pub fn write_lcov(mods: &[ModuleCoverage], path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut out = String::new();
+    for m in mods {
+        for file in &m.files {
+            let totals = file.totals();
+            writeln!(out, "TN:{}", m.name)?;
+            writeln!(out, "SF:{}", file.path.display())?;
+            for (line, list) in &file.branches {
+                for (i, taken) in list.iter().enumerate() {
+                    let taken = taken.map_or("-".to_string(), |n| n.to_string());
+                    writeln!(out, "BRDA:{},0,{},{}", line, i, taken)?;
+                }
+            }
+            writeln!(out, "BRF:{}", totals.branches_found)?;
+            writeln!(out, "BRH:{}", totals.branches_hit)?;
+            for (line, count) in &file.lines {
+                writeln!(out, "DA:{},{}", line, count)?;
+            }
+            writeln!(out, "LF:{}", totals.lines_found)?;
+            writeln!(out, "LH:{}", totals.lines_hit)?;
+            writeln!(out, "end_of_record")?;
+        }
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, out)?;
+    Ok(())
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }\n\
+     table { border-collapse: collapse; }\n\
+     th, td { border-bottom: 1px solid #ddd; padding: 4px 12px; text-align: left; }\n\
+     td.num { text-align: right; font-family: monospace; }\n\
+     pre { margin: 0; }\n\
+     .hit { background: #dfd; }\n\
+     .miss { background: #fdd; }\n\
+     .src td { border: none; padding: 0 8px; font-family: monospace; white-space: pre; }\n";
+
+/// 写出 HTML 报告：index.html 汇总，每个源文件一页并标出未执行的行，返回 index.html 路径
+pub fn write_html(mods: &[ModuleCoverage], dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let row = |name: String, totals: Totals| {
+        let (lines, branches) = summary(totals);
+        format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+            name,
+            escape(&lines),
+            escape(&branches)
+        )
+    };
+
+    let mut rows = String::new();
+    for m in mods {
+        rows.push_str(&row(format!("<b>{}</b>", escape(&m.name)), m.totals()));
+        for (i, file) in m.files.iter().enumerate() {
+            let page = format!("{}-{}.html", m.name, i);
+            write_file_page(file, &m.display(file), &dir.join(&page))?;
+            rows.push_str(&row(
+                format!(
+                    "&nbsp;&nbsp;<a href=\"{}\">{}</a>",
+                    escape(&page),
+                    escape(&m.display(file))
+                ),
+                file.totals(),
+            ));
+        }
+    }
+    rows.push_str(&row("<b>total</b>".to_string(), project_totals(mods)));
+
+    let index = dir.join("index.html");
+    fs::write(
+        &index,
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>hk coverage</title>\n\
+             <style>\n{}</style>\n</head>\n<body>\n<h1>hk coverage</h1>\n<table>\n\
+             <tr><th>module / file</th><th>lines</th><th>branches</th></tr>\n{}</table>\n</body>\n</html>\n",
+            HTML_STYLE, rows
+        ),
+    )?;
+    Ok(index)
+}
+
+fn write_file_page(file: &FileCoverage, title: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(&file.path).unwrap_or_default();
+    let mut rows = String::new();
+    for (i, text) in source.lines().enumerate() {
+        let number = i as u32 + 1;
+        let (class, count) = match file.lines.get(&number) {
+            Some(0) => (" class=\"miss\"", "0".to_string()),
+            Some(n) => (" class=\"hit\"", n.to_string()),
+            None => ("", String::new()),
+        };
+        let branches = file.branches.get(&number).map_or(String::new(), |list| {
+            let hit = list.iter().filter(|b| b.is_some_and(|n| n > 0)).count();
+            format!("{}/{}", hit, list.len())
+        });
+        writeln!(
+            rows,
+            "<tr{}><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+            class,
+            number,
+            count,
+            branches,
+            escape(text)
+        )?;
+    }
+    fs::write(
+        path,
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+             <style>\n{1}</style>\n</head>\n<body>\n<p><a href=\"index.html\">index</a></p>\n\
+             <h1>{0}</h1>\n<table class=\"src\">\n\
+             <tr><th>line</th><th>count</th><th>branches</th><th>source</th></tr>\n{2}</table>\n</body>\n</html>\n",
+            escape(title),
+            HTML_STYLE,
+            rows
+        ),
+    )?;
+    Ok(())
+}
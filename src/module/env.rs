@@ -0,0 +1,350 @@
+use crate::logi;
+use crate::utils::diagnostics::{self, Diagnostics, MessageFormat};
+use crate::utils::probe::{self, CompilerInfo, CompilerKind};
+use crate::utils::progress::Progress;
+use crate::utils::shell;
+use crate::utils::timings::Timings;
+use crate::utils::toolchain::Toolchain;
+use crate::utils::yaml::{Compiler, Sanitizer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// 构建配置档：debug / release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    /// 从命令行字符串解析构建配置档（不区分大小写）
+    pub fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        if value.eq_ignore_ascii_case("debug") {
+            Ok(Profile::Debug)
+        } else if value.eq_ignore_ascii_case("release") {
+            Ok(Profile::Release)
+        } else {
+            Err(format!("Unknown build mode: {} (expected debug or release)", value).into())
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Profile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Profile::parse(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Profile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// --message-format json 输出的一条编译诊断
+#[derive(Serialize)]
+struct CompilerMessage<'a> {
+    reason: &'static str,
+    module: &'a str,
+    message: &'a diagnostics::Diagnostic,
+}
+
+/// --message-format json 输出的一个测试程序的结果
+#[derive(Serialize)]
+pub struct TestMessage<'a> {
+    pub reason: &'static str,
+    pub module: &'a str,
+    pub name: &'a str,
+    pub passed: bool,
+}
+
+/// 一次命令执行期间共享的构建环境，用于在加载配置时求值条件块
+#[derive(Debug, Clone)]
+pub struct BuildEnv {
+    /// 当前构建配置档
+    pub profile: Profile,
+    /// 运行 hk 的主机三元组，如 x86_64-unknown-linux-gnu
+    pub host: String,
+    /// 通过 --toolchain 选择的工具链，覆盖所有模块的编译器配置
+    pub toolchain: Option<Toolchain>,
+    /// detect_env 阶段探测到的编译器信息，键为编译器名加目标参数（见 probe::probe_key）
+    pub compilers: BTreeMap<String, CompilerInfo>,
+    /// 离线模式：禁止克隆或拉取外部依赖
+    pub offline: bool,
+    /// 输出每条执行的命令（已按 shell 规则加引号）
+    pub verbose: bool,
+    /// 只按顺序输出编译、归档与链接命令，不实际执行
+    pub dry_run: bool,
+    /// 构建进度显示
+    pub progress: Arc<Progress>,
+    /// --timings 的计时记录
+    pub timings: Arc<Timings>,
+    /// 模块构建失败后继续构建不依赖它的模块
+    pub keep_going: bool,
+    /// 失败步骤捕获的输出，模块失败时取出写入最终报告
+    pub failures: Arc<Mutex<Vec<String>>>,
+    /// --deny-warnings：所有模块的警告都视为错误
+    pub deny_warnings: bool,
+    /// 启用的 sanitizer，编译与链接所有模块时使用，产物放在单独的目录
+    pub sanitize: Vec<Sanitizer>,
+    /// 是否启用模块配置中的 sanitize，仅 build/run/test 启用
+    pub config_sanitize: bool,
+    /// hk coverage：以 --coverage 插桩编译与链接，产物放在 build/coverage
+    pub coverage: bool,
+    /// 编译诊断的输出格式
+    pub message_format: MessageFormat,
+    /// 已输出的编译诊断，用于去重与统计各模块警告数
+    pub diagnostics: Arc<Diagnostics>,
+}
+
+impl BuildEnv {
+    pub fn new(profile: Profile) -> Self {
+        BuildEnv {
+            profile,
+            host: Self::host_triple(),
+            toolchain: None,
+            compilers: BTreeMap::new(),
+            offline: false,
+            verbose: false,
+            dry_run: false,
+            progress: Arc::new(Progress::new()),
+            timings: Arc::new(Timings::new()),
+            keep_going: false,
+            failures: Arc::new(Mutex::new(Vec::new())),
+            deny_warnings: false,
+            sanitize: Vec::new(),
+            config_sanitize: false,
+            coverage: false,
+            message_format: MessageFormat::Human,
+            diagnostics: Arc::new(Diagnostics::new()),
+        }
+    }
+
+    /// 执行构建步骤（编译、归档、链接等）的命令，返回是否成功；what 为进度行中显示的对象。
+    /// verbose 时先输出命令，dry-run 时只输出不执行；命令输出在进度行清除后整体转发
+    pub fn exec(&self, step: &str, what: &str, cmd: &mut Command) -> Result<bool, Box<dyn Error>> {
+        let line = shell::quote_cmd(cmd);
+        logi!("{}: {}", step, line);
+        self.progress.step(step, what);
+        if self.verbose || self.dry_run {
+            self.progress.println(&line);
+        }
+        if self.dry_run {
+            return Ok(true);
+        }
+        let start = Instant::now();
+        let output = cmd
+            .output()
+            .map_err(|e| format!("Failed to run {:?}: {}", cmd.get_program(), e))?;
+        let module = what.split('/').next().unwrap_or_default();
+        self.timings.record(
+            "step",
+            &format!("{} {}", step, what),
+            module,
+            start,
+            output.status.success(),
+        );
+        let json = cmd.get_args().any(|arg| arg == diagnostics::JSON_FLAG);
+        self.forward_output(module, &output, json)?;
+        Ok(output.status.success())
+    }
+
+    /// 解析命令输出中的诊断（编译器写到 stderr，clang-tidy 等写到 stdout），去重后按 message_format 输出；
+    /// 命令失败时把其诊断记入 failures
+    fn forward_output(
+        &self,
+        module: &str,
+        output: &Output,
+        json: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let (mut found, other) = if json {
+            diagnostics::parse_json(&stderr)
+        } else {
+            diagnostics::parse_text(&stderr)
+        };
+        let (stdout_found, stdout_other) =
+            diagnostics::parse_text(&String::from_utf8_lossy(&output.stdout));
+        found.splice(0..0, stdout_found);
+        if !output.status.success() {
+            let text: String = std::iter::once(stdout_other.as_str())
+                .chain(found.iter().map(|d| d.rendered.as_str()))
+                .chain(std::iter::once(other.as_str()))
+                .collect();
+            self.failures
+                .lock()
+                .unwrap()
+                .push(text.trim_end().to_string());
+        }
+
+        let fresh = self.diagnostics.collect(module, found);
+        if stdout_other.is_empty() && fresh.is_empty() && other.is_empty() {
+            return Ok(());
+        }
+        self.progress.clear();
+        match self.message_format {
+            MessageFormat::Human => {
+                std::io::stdout().write_all(stdout_other.as_bytes())?;
+                let mut err = std::io::stderr().lock();
+                for diag in &fresh {
+                    err.write_all(diag.rendered.as_bytes())?;
+                }
+                err.write_all(other.as_bytes())?;
+            }
+            MessageFormat::Json => {
+                // stdout 只输出 JSON 消息，其余输出改到 stderr
+                std::io::stderr().write_all(stdout_other.as_bytes())?;
+                std::io::stderr().write_all(other.as_bytes())?;
+                for diag in &fresh {
+                    diagnostics::emit_json(&CompilerMessage {
+                        reason: "compiler-message",
+                        module,
+                        message: diag,
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 创建构建输出目录，dry-run 时不写文件系统
+    pub fn create_dir_all(&self, dir: &Path) -> std::io::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        std::fs::create_dir_all(dir)
+    }
+
+    /// 取出并清空已记录的失败步骤输出
+    pub fn take_failures(&self) -> Vec<String> {
+        std::mem::take(&mut *self.failures.lock().unwrap())
+    }
+
+    /// 运行构建产物（可执行文件、测试程序），继承终端的输入输出
+    pub fn run_program(&self, step: &str, cmd: &mut Command) -> Result<bool, Box<dyn Error>> {
+        let line = shell::quote_cmd(cmd);
+        logi!("{}: {}", step, line);
+        self.progress.clear();
+        if self.verbose || self.dry_run {
+            match self.message_format {
+                MessageFormat::Human => println!("{}", line),
+                MessageFormat::Json => self.progress.println(&line),
+            }
+        }
+        if self.dry_run {
+            return Ok(true);
+        }
+        // stdout 只输出 JSON 消息，程序输出改到 stderr
+        if self.message_format == MessageFormat::Json {
+            cmd.stdout(std::io::stderr());
+        }
+        let status = cmd
+            .status()
+            .map_err(|e| format!("Failed to run {:?}: {}", cmd.get_program(), e))?;
+        Ok(status.success())
+    }
+
+    /// 模块的构建输出目录：build/，覆盖率构建为 build/coverage，
+    /// 启用 sanitizer 时为 build/sanitize-<名字>（两者同时启用时合并为 coverage-sanitize-<名字>）
+    pub fn build_dir(&self, root: &Path) -> PathBuf {
+        let build = root.join("build");
+        let mut parts = Vec::new();
+        if self.coverage {
+            parts.push("coverage".to_string());
+        }
+        if !self.sanitize.is_empty() {
+            let names: Vec<&str> = self.sanitize.iter().map(|s| s.as_str()).collect();
+            parts.push(format!("sanitize-{}", names.join("-")));
+        }
+        if parts.is_empty() {
+            build
+        } else {
+            build.join(parts.join("-"))
+        }
+    }
+
+    /// 编译与链接都要加的 -fsanitize= 参数
+    pub fn sanitize_flag(&self) -> Option<String> {
+        if self.sanitize.is_empty() {
+            return None;
+        }
+        let names: Vec<&str> = self.sanitize.iter().map(|s| s.as_str()).collect();
+        Some(format!("-fsanitize={}", names.join(",")))
+    }
+
+    /// 运行产物时 sanitizer 的默认选项，用户已设置的环境变量优先
+    pub fn sanitizer_options(&self) -> Vec<(&'static str, &'static str)> {
+        let mut options = Vec::new();
+        for sanitizer in &self.sanitize {
+            match sanitizer {
+                Sanitizer::Address => options.push((
+                    "ASAN_OPTIONS",
+                    "abort_on_error=1:detect_leaks=1:check_initialization_order=1:strict_string_checks=1",
+                )),
+                Sanitizer::Undefined => {
+                    options.push(("UBSAN_OPTIONS", "print_stacktrace=1:halt_on_error=1"))
+                }
+                Sanitizer::Thread => {
+                    options.push(("TSAN_OPTIONS", "halt_on_error=1:second_deadlock_stack=1"))
+                }
+                Sanitizer::Leak => options.push(("LSAN_OPTIONS", "print_suppressions=0")),
+            }
+        }
+        options
+    }
+
+    /// 查询已探测的编译器种类
+    pub fn compiler_kind(&self, cc: &str) -> Option<CompilerKind> {
+        self.compilers
+            .values()
+            .find(|info| info.cc == cc)
+            .map(|info| info.kind)
+    }
+
+    /// 是否为 clang 系编译器：优先使用探测结果，未探测时按编译器名判断
+    pub fn is_clang(&self, compiler: &Compiler) -> bool {
+        match self.compiler_kind(&compiler.cc) {
+            Some(kind) => kind == CompilerKind::Clang,
+            None => compiler.is_clang(),
+        }
+    }
+
+    /// 模块编译器配置对应的探测结果，按编译器与目标参数区分
+    pub fn compiler_info(&self, compiler: &Compiler) -> Option<&CompilerInfo> {
+        let args = compiler.target_args(self.is_clang(compiler));
+        self.compilers.get(&probe::probe_key(&compiler.cc, &args))
+    }
+
+    /// 根据编译 hk 时的架构与系统推断主机三元组
+    pub fn host_triple() -> String {
+        let arch = std::env::consts::ARCH;
+        match std::env::consts::OS {
+            "linux" => format!("{}-unknown-linux-gnu", arch),
+            "macos" => format!("{}-apple-darwin", arch),
+            "windows" => format!("{}-pc-windows-msvc", arch),
+            os => format!("{}-unknown-{}", arch, os),
+        }
+    }
+}
+
+impl Default for BuildEnv {
+    fn default() -> Self {
+        Self::new(Profile::Debug)
+    }
+}
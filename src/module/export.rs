@@ -0,0 +1,358 @@
+use crate::module::manager::ModMgr;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 库模块对外导出的信息，用于生成 pkg-config 与 CMake 包文件
+#[derive(Debug, Clone)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    /// 构建树中的头文件目录（本模块 include/、依赖模块 include/ 与显式 include）
+    pub include_dirs: Vec<PathBuf>,
+    /// 加入头文件搜索路径的 include/ 子目录（相对 include/），安装后位于 includedir 下
+    pub include_subdirs: Vec<PathBuf>,
+    /// 本模块的预定义宏
+    pub defines: Vec<String>,
+    /// 传递依赖模块的预定义宏
+    pub dep_defines: Vec<String>,
+    /// 本模块静态库
+    pub lib_file: PathBuf,
+    /// 直接依赖且产出库的模块名
+    pub requires: Vec<String>,
+    /// 传递依赖模块的静态库，按链接顺序排列（被依赖者在后）
+    pub dep_lib_files: Vec<PathBuf>,
+    /// 本模块用到的系统库
+    pub sys_libs: Vec<String>,
+    /// 传递依赖模块用到的系统库（不含本模块已有的）
+    pub dep_sys_libs: Vec<String>,
+    /// 本模块的 pkg-config 依赖规格，如 "openssl >= 1.1"
+    pub pkg_requires: Vec<String>,
+    /// 本模块及传递依赖经 pkg-config 解析出的编译参数
+    pub pkg_cflags: Vec<String>,
+    /// 本模块及传递依赖经 pkg-config 解析出的链接参数
+    pub pkg_libs: Vec<String>,
+}
+
+impl ModMgr {
+    /// 汇总模块的导出信息，模块未产出库时返回 None
+    pub fn package_info(&self, name: &str) -> Result<Option<PackageInfo>, Box<dyn Error>> {
+        let map = &self.project_map;
+        let modfile = map
+            .indices
+            .get(name)
+            .ok_or_else(|| format!("Unknown module: {}", name))?;
+        let Some(lib_file) = modfile.library() else {
+            return Ok(None);
+        };
+        let config = modfile.config()?;
+
+        // 深度优先后序遍历得到依赖的拓扑序，再反转为链接顺序
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        self.visit_deps(name, &mut visited, &mut order)?;
+        order.retain(|n| n != name);
+        order.reverse();
+
+        let mut dep_lib_files = Vec::new();
+        let mut dep_sys_libs: Vec<String> = Vec::new();
+        let mut dep_defines: Vec<String> = Vec::new();
+        let mut pkg_cflags = modfile.pkg_flags.cflags.clone();
+        let mut pkg_libs = modfile.pkg_flags.libs.clone();
+        for dep in &order {
+            let dep_mod = &map.indices[dep];
+            if let Some(lib) = dep_mod.library() {
+                dep_lib_files.push(lib);
+            }
+            let dep_config = dep_mod.config()?;
+            for flag in &dep_mod.pkg_flags.cflags {
+                if !pkg_cflags.contains(flag) {
+                    pkg_cflags.push(flag.clone());
+                }
+            }
+            pkg_libs.extend(dep_mod.pkg_flags.libs.iter().cloned());
+            for lib in &dep_config.dep.lib {
+                if !config.dep.lib.contains(lib) && !dep_sys_libs.contains(lib) {
+                    dep_sys_libs.push(lib.clone());
+                }
+            }
+            for def in &dep_config.premacro {
+                if !config.premacro.contains(def) && !dep_defines.contains(def) {
+                    dep_defines.push(def.clone());
+                }
+            }
+        }
+
+        let requires = config
+            .dep
+            .dep_names()
+            .into_iter()
+            // 只有产出库的依赖模块才有对应的包文件
+            .filter(|d| map.indices.get(d).is_some_and(|m| m.library().is_some()))
+            .collect();
+
+        let include_dirs = modfile.include_paths.clone().unwrap_or_default();
+        let include_root = fs::canonicalize(modfile.absolute_path.join("include")).ok();
+        let include_subdirs = include_dirs
+            .iter()
+            .filter_map(|d| d.strip_prefix(include_root.as_ref()?).ok())
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .collect();
+
+        Ok(Some(PackageInfo {
+            name: name.to_string(),
+            version: config.version.clone(),
+            include_dirs,
+            include_subdirs,
+            defines: config.premacro.clone(),
+            dep_defines,
+            lib_file,
+            requires,
+            dep_lib_files,
+            sys_libs: config.dep.lib.clone(),
+            dep_sys_libs,
+            pkg_requires: config.dep.pkg_config.clone(),
+            pkg_cflags,
+            pkg_libs,
+        }))
+    }
+
+    pub(crate) fn visit_deps(
+        &self,
+        name: &str,
+        visited: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !visited.insert(name.to_string()) {
+            return Ok(());
+        }
+        let config = self.project_map.indices[name].config()?;
+        for dep in config.dep.dep_names() {
+            if self.project_map.indices.contains_key(&dep) {
+                self.visit_deps(&dep, visited, order)?;
+            }
+        }
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    /// 在模块 build/ 下生成构建树版本的包文件：
+    /// build/pkgconfig/<name>.pc 与 build/cmake/<name>Config.cmake
+    pub fn write_exports(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let Some(info) = self.package_info(name)? else {
+            return Ok(());
+        };
+        let build_dir = &self.project_map.indices[name].build_dir;
+
+        let pc_dir = build_dir.join("pkgconfig");
+        fs::create_dir_all(&pc_dir)?;
+        fs::write(pc_dir.join(format!("{}.pc", name)), info.build_tree_pc())?;
+
+        let cmake_dir = build_dir.join("cmake");
+        fs::create_dir_all(&cmake_dir)?;
+        fs::write(
+            cmake_dir.join(format!("{}Config.cmake", name)),
+            info.build_tree_cmake(),
+        )?;
+        Ok(())
+    }
+}
+
+impl PackageInfo {
+    /// 构建树版本 .pc：全部使用绝对路径，并展开传递依赖，无需其他 .pc
+    pub fn build_tree_pc(&self) -> String {
+        let mut cflags: Vec<String> = self
+            .include_dirs
+            .iter()
+            .map(|d| format!("-I{}", d.display()))
+            .collect();
+        cflags.extend(
+            self.defines
+                .iter()
+                .chain(&self.dep_defines)
+                .map(|d| format!("-D{}", d)),
+        );
+        cflags.extend(self.pkg_cflags.iter().cloned());
+
+        let mut libs = vec![self.lib_file.display().to_string()];
+        libs.extend(self.dep_lib_files.iter().map(|l| l.display().to_string()));
+        libs.extend(
+            self.sys_libs
+                .iter()
+                .chain(&self.dep_sys_libs)
+                .map(|l| format!("-l{}", l)),
+        );
+        libs.extend(self.pkg_libs.iter().cloned());
+
+        format!(
+            "Name: {}\n\
+             Description: {} library (hk build tree)\n\
+             Version: {}\n\
+             Cflags: {}\n\
+             Libs: {}\n",
+            self.name,
+            self.name,
+            self.version,
+            cflags.join(" "),
+            libs.join(" ")
+        )
+    }
+
+    /// 安装版本 .pc：路径相对 prefix，模块依赖与 pkg-config 依赖通过 Requires 引用各自的 .pc
+    pub fn installed_pc(&self, prefix: &Path) -> String {
+        let mut cflags = vec!["-I${includedir}".to_string()];
+        cflags.extend(
+            self.include_subdirs
+                .iter()
+                .map(|d| format!("-I${{includedir}}/{}", d.display())),
+        );
+        cflags.extend(self.defines.iter().map(|d| format!("-D{}", d)));
+
+        let mut libs = vec!["-L${libdir}".to_string(), format!("-l{}", self.name)];
+        libs.extend(self.sys_libs.iter().map(|l| format!("-l{}", l)));
+
+        let mut text = format!(
+            "prefix={}\n\
+             exec_prefix=${{prefix}}\n\
+             libdir=${{exec_prefix}}/lib\n\
+             includedir=${{prefix}}/include\n\
+             \n\
+             Name: {}\n\
+             Description: {} library built by hk\n\
+             Version: {}\n",
+            prefix.display(),
+            self.name,
+            self.name,
+            self.version
+        );
+        let requires: Vec<&String> = self.requires.iter().chain(&self.pkg_requires).collect();
+        if !requires.is_empty() {
+            let requires: Vec<&str> = requires.iter().map(|r| r.as_str()).collect();
+            text.push_str(&format!("Requires: {}\n", requires.join(", ")));
+        }
+        text.push_str(&format!("Cflags: {}\n", cflags.join(" ")));
+        text.push_str(&format!("Libs: {}\n", libs.join(" ")));
+        text
+    }
+
+    /// 构建树版本 CMake 包文件：导入目标 hk::<name>，依赖库以绝对路径展开
+    pub fn build_tree_cmake(&self) -> String {
+        let includes: Vec<String> = self
+            .include_dirs
+            .iter()
+            .map(|d| d.display().to_string())
+            .collect();
+        let mut link: Vec<String> = self
+            .dep_lib_files
+            .iter()
+            .map(|l| l.display().to_string())
+            .collect();
+        link.extend(self.sys_libs.iter().chain(&self.dep_sys_libs).cloned());
+        link.extend(self.pkg_libs.iter().cloned());
+        let defines: Vec<String> = self
+            .defines
+            .iter()
+            .chain(&self.dep_defines)
+            .cloned()
+            .collect();
+
+        self.cmake_target(
+            "",
+            &self.lib_file.display().to_string(),
+            &includes.join(";"),
+            &defines,
+            &self.pkg_cflags,
+            &link.join(";"),
+        )
+    }
+
+    /// 安装版本 CMake 包文件：安装在 <prefix>/lib/cmake/<name>/，按自身位置推算前缀，
+    /// 模块依赖通过 find_dependency 引入对应的 hk::<dep> 目标，pkg-config 依赖通过 PkgConfig 模块导入
+    pub fn installed_cmake(&self) -> String {
+        let mut prologue = String::from(
+            "get_filename_component(_hk_prefix \"${CMAKE_CURRENT_LIST_DIR}/../../..\" ABSOLUTE)\n",
+        );
+        if !self.requires.is_empty() {
+            prologue.push_str("include(CMakeFindDependencyMacro)\n");
+            for dep in &self.requires {
+                prologue.push_str(&format!("find_dependency({})\n", dep));
+            }
+        }
+
+        let mut link: Vec<String> = self.requires.iter().map(|d| format!("hk::{}", d)).collect();
+        link.extend(self.sys_libs.iter().cloned());
+
+        if !self.pkg_requires.is_empty() {
+            prologue.push_str("find_package(PkgConfig REQUIRED)\n");
+            for spec in &self.pkg_requires {
+                let name = spec.split_whitespace().next().unwrap_or(spec);
+                let var = format!(
+                    "_hk_pc_{}",
+                    name.replace(|c: char| !c.is_alphanumeric(), "_")
+                );
+                prologue.push_str(&format!(
+                    "pkg_check_modules({} REQUIRED IMPORTED_TARGET \"{}\")\n",
+                    var, spec
+                ));
+                link.push(format!("PkgConfig::{}", var));
+            }
+        }
+
+        let mut includes = vec!["${_hk_prefix}/include".to_string()];
+        includes.extend(
+            self.include_subdirs
+                .iter()
+                .map(|d| format!("${{_hk_prefix}}/include/{}", d.display())),
+        );
+
+        self.cmake_target(
+            &prologue,
+            &format!("${{_hk_prefix}}/lib/lib{}.a", self.name),
+            &includes.join(";"),
+            &self.defines,
+            &[],
+            &link.join(";"),
+        )
+    }
+
+    fn cmake_target(
+        &self,
+        prologue: &str,
+        location: &str,
+        includes: &str,
+        defines: &[String],
+        options: &[String],
+        link: &str,
+    ) -> String {
+        let target = format!("hk::{}", self.name);
+        let mut text = format!(
+            "# Generated by hk, do not edit\n\
+             if(TARGET {target})\n  return()\nendif()\n\
+             {prologue}\
+             add_library({target} STATIC IMPORTED)\n\
+             set_target_properties({target} PROPERTIES\n  \
+             IMPORTED_LOCATION \"{location}\"\n  \
+             INTERFACE_INCLUDE_DIRECTORIES \"{includes}\"\n"
+        );
+        if !defines.is_empty() {
+            text.push_str(&format!(
+                "  INTERFACE_COMPILE_DEFINITIONS \"{}\"\n",
+                defines.join(";")
+            ));
+        }
+        if !options.is_empty() {
+            text.push_str(&format!(
+                "  INTERFACE_COMPILE_OPTIONS \"{}\"\n",
+                options.join(";")
+            ));
+        }
+        if !link.is_empty() {
+            text.push_str(&format!("  INTERFACE_LINK_LIBRARIES \"{}\"\n", link));
+        }
+        text.push_str(")\n");
+        text.push_str(&format!("set({}_FOUND TRUE)\n", self.name));
+        text
+    }
+}
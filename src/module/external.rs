@@ -0,0 +1,834 @@
+use crate::logi;
+use crate::module::package::{unpack_archive, verify_archive, PackageManifest};
+use crate::module::{env::BuildEnv, manager::ModMgr, mod_file::ModFile};
+use crate::utils::git;
+use crate::utils::yaml::{Config, External};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// 锁文件中记录的 git 依赖
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LockedDep {
+    pub name: String,
+    pub git: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// 解析出的完整提交哈希
+    pub commit: String,
+}
+
+/// 项目根目录下的 hk.lock，记录 git 依赖的确切提交
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LockFile {
+    #[serde(default)]
+    pub package: Vec<LockedDep>,
+}
+
+impl LockFile {
+    pub fn path(project_root: &Path) -> PathBuf {
+        project_root.join("hk.lock")
+    }
+
+    /// 加载锁文件，不存在时返回空锁
+    pub fn load(project_root: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = Self::path(project_root);
+        if !path.exists() {
+            return Ok(LockFile::default());
+        }
+        let text = fs::read_to_string(&path)?;
+        serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+    }
+
+    /// 锁定的条目，仅在仓库地址与 rev 均未改动时有效
+    pub fn find(&self, name: &str, git: &str, rev: Option<&str>) -> Option<&LockedDep> {
+        self.package
+            .iter()
+            .find(|p| p.name == name && p.git == git && p.rev.as_deref() == rev)
+    }
+
+    pub fn save(&self, project_root: &Path) -> Result<(), Box<dyn Error>> {
+        let text = format!(
+            "# Generated by hk, do not edit\n{}",
+            serde_yaml::to_string(self)?
+        );
+        fs::write(Self::path(project_root), text)?;
+        Ok(())
+    }
+}
+
+/// 已解析的外部依赖模块
+#[derive(Debug, Clone)]
+pub struct ResolvedExternal {
+    /// 声明时的配置
+    pub spec: External,
+    /// 模块目录（本地路径、git 检出目录或 vendor/ 下的副本）
+    pub dir: PathBuf,
+    /// git 依赖检出的提交
+    pub commit: Option<String>,
+}
+
+/// vendor/<name>/ 中记录来源的标记文件，声明未改动时构建直接使用该副本
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct VendorInfo {
+    #[serde(flatten)]
+    source: External,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+}
+
+const VENDOR_INFO: &str = ".hk-vendor.yaml";
+
+/// 外部依赖在项目中的 vendor 目录
+pub fn vendor_dir(project_root: &Path, name: &str) -> PathBuf {
+    project_root.join("vendor").join(name)
+}
+
+/// 查找与声明来源一致的 vendor 副本：名字相同，git 依赖比较仓库地址与提交（有锁定提交时比较提交，
+/// 否则比较 rev），本地路径与归档比较路径与校验和；version 等字段的改动不影响使用副本
+fn vendored(project_root: &Path, ext: &External, lock: &LockFile) -> Option<VendorInfo> {
+    let text = fs::read_to_string(vendor_dir(project_root, &ext.name).join(VENDOR_INFO)).ok()?;
+    let info: VendorInfo = serde_yaml::from_str(&text).ok()?;
+    let vendored = &info.source;
+    let same = vendored.name == ext.name
+        && match (&ext.path, &ext.git, &ext.archive) {
+            (Some(path), None, None) => vendored.path.as_ref() == Some(path),
+            (None, Some(url), None) => {
+                vendored.git.as_ref() == Some(url)
+                    && match lock.find(&ext.name, url, ext.rev.as_deref()) {
+                        Some(locked) => info.commit.as_ref() == Some(&locked.commit),
+                        None => vendored.rev == ext.rev,
+                    }
+            }
+            (None, None, Some(archive)) => {
+                vendored.archive.as_ref() == Some(archive)
+                    && (ext.sha256.is_none() || vendored.sha256 == ext.sha256)
+            }
+            _ => false,
+        };
+    same.then_some(info)
+}
+
+/// 外部依赖的来源，用于判断多个模块声明的同名依赖是否一致
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    Path(PathBuf),
+    Git {
+        url: String,
+        rev: Option<String>,
+    },
+    Archive {
+        path: PathBuf,
+        sha256: Option<String>,
+    },
+    Vendor {
+        dir: PathBuf,
+        commit: Option<String>,
+    },
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Path(dir) => write!(f, "path {}", dir.display()),
+            Source::Git { url, rev } => {
+                write!(f, "git {} ({})", url, rev.as_deref().unwrap_or("HEAD"))
+            }
+            Source::Archive { path, .. } => write!(f, "archive {}", path.display()),
+            Source::Vendor { dir, .. } => write!(f, "vendored {}", dir.display()),
+        }
+    }
+}
+
+impl Source {
+    fn of(ext: &External, decl_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        match (&ext.path, &ext.git, &ext.archive) {
+            (Some(path), None, None) => {
+                let dir = decl_dir.join(path);
+                let dir = fs::canonicalize(&dir).map_err(|e| {
+                    format!(
+                        "External module '{}' not found at {}: {}",
+                        ext.name,
+                        dir.display(),
+                        e
+                    )
+                })?;
+                Ok(Source::Path(dir))
+            }
+            (None, Some(url), None) => Ok(Source::Git {
+                url: url.clone(),
+                rev: ext.rev.clone(),
+            }),
+            (None, None, Some(archive)) => {
+                let path = decl_dir.join(archive);
+                let path = fs::canonicalize(&path).map_err(|e| {
+                    format!(
+                        "Archive of external module '{}' not found at {}: {}",
+                        ext.name,
+                        path.display(),
+                        e
+                    )
+                })?;
+                Ok(Source::Archive {
+                    path,
+                    sha256: ext.sha256.clone(),
+                })
+            }
+            _ => Err(format!(
+                "External module '{}' must set exactly one of `path`, `git` or `archive`",
+                ext.name
+            )
+            .into()),
+        }
+    }
+}
+
+/// 本地仓库的相对路径按声明模块目录解析，URL 与 scp 形式的地址原样使用；
+/// 拒绝以 - 开头的地址与 <transport>::<address> 形式的远程辅助程序（如 ext::）
+fn clone_url(url: &str, decl_dir: &Path) -> Result<String, Box<dyn Error>> {
+    if url.starts_with('-') || url.contains("::") {
+        return Err(format!("Invalid git URL '{}'", url).into());
+    }
+    if url.contains(':') || Path::new(url).is_absolute() {
+        Ok(url.to_string())
+    } else {
+        Ok(decl_dir.join(url).display().to_string())
+    }
+}
+
+/// 检出 git 依赖：锁文件中有对应条目时使用锁定提交，否则解析 rev 并返回新的提交；
+/// 离线模式下只使用已有的检出，需要克隆或拉取时报错
+fn checkout_git(
+    name: &str,
+    url: &str,
+    rev: Option<&str>,
+    dir: &Path,
+    locked: Option<&LockedDep>,
+    offline: bool,
+) -> Result<String, Box<dyn Error>> {
+    let fetch = |dir: &Path| -> Result<(), Box<dyn Error>> {
+        if offline {
+            return Err(format!(
+                "External module '{}' needs to fetch {} but --offline is set (run `hk vendor` while online)",
+                name, url
+            )
+            .into());
+        }
+        git::fetch(dir)
+    };
+
+    if let Some(rev) = rev {
+        git::check_rev(rev).map_err(|e| format!("External module '{}': {}", name, e))?;
+    }
+    if let Some(locked) = locked {
+        git::check_rev(&locked.commit)
+            .map_err(|e| format!("External module '{}' in hk.lock: {}", name, e))?;
+    }
+
+    let fresh = !dir.join(".git").exists();
+    if fresh {
+        if offline {
+            return Err(format!(
+                "External module '{}' is not checked out and --offline forbids cloning {} (run `hk vendor` while online)",
+                name, url
+            )
+            .into());
+        }
+        logi!("Cloning external module {} from {}", name, url);
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        git::clone(url, dir)?;
+    }
+
+    let commit = match locked {
+        Some(locked) => {
+            if git::rev_parse(dir, &locked.commit).is_none() {
+                fetch(dir)?;
+            }
+            git::rev_parse(dir, &locked.commit).ok_or_else(|| {
+                format!(
+                    "Locked commit {} of external module '{}' not found in {}",
+                    locked.commit, name, url
+                )
+            })?
+        }
+        None => {
+            if !fresh {
+                fetch(dir)?;
+            }
+            // 分支优先按远端解析，其次是标签与提交
+            let candidates = match rev {
+                Some(rev) => vec![format!("origin/{}", rev), rev.to_string()],
+                None => vec!["origin/HEAD".to_string()],
+            };
+            candidates
+                .iter()
+                .find_map(|c| git::rev_parse(dir, c))
+                .ok_or_else(|| {
+                    format!(
+                        "Revision '{}' of external module '{}' not found in {}",
+                        rev.unwrap_or("HEAD"),
+                        name,
+                        url
+                    )
+                })?
+        }
+    };
+
+    if git::head(dir).as_deref() != Some(commit.as_str()) {
+        logi!("Checking out {} at {}", name, commit);
+        git::checkout(dir, &commit)?;
+    }
+    Ok(commit)
+}
+
+/// 解压归档依赖到 dir：校验归档 sha256，同一归档已解压过时直接复用
+fn unpack_external(
+    name: &str,
+    archive: &Path,
+    sha256: Option<&str>,
+    dir: &Path,
+) -> Result<PackageManifest, Box<dyn Error>> {
+    let checksum = verify_archive(archive, sha256)?;
+    let marker = dir.join(ARCHIVE_MARKER);
+    let manifest = if fs::read_to_string(&marker).ok().as_deref() == Some(checksum.as_str()) {
+        PackageManifest::load(dir)?
+    } else {
+        logi!("Unpacking external module {} from {:?}", name, archive);
+        let manifest = unpack_archive(archive, dir)?;
+        fs::write(&marker, &checksum)?;
+        manifest
+    };
+    if manifest.name != name {
+        return Err(format!(
+            "Archive {} contains module '{}', expected '{}'",
+            archive.display(),
+            manifest.name,
+            name
+        )
+        .into());
+    }
+    Ok(manifest)
+}
+
+/// 解压目录中记录归档 sha256 的标记文件
+const ARCHIVE_MARKER: &str = ".hk-archive";
+
+impl ModMgr {
+    /// 解析所有模块声明的外部依赖：与声明一致的 vendor/<name> 副本优先，本地路径直接引用，
+    /// git 仓库检出、归档解压到 .hk/deps/<name>，外部模块作为普通模块加入项目（其自身的外部依赖同样解析），
+    /// 最后更新 hk.lock
+    pub fn resolve_externals(&mut self, root: &Path, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        let old_lock = LockFile::load(root)?;
+        let mut lock = LockFile::default();
+        let mut resolved: HashMap<String, (Source, PathBuf)> = HashMap::new();
+        let mut pending = self.project_map.mod_names.clone();
+
+        while let Some(name) = pending.pop() {
+            let modfile = &self.project_map.indices[&name];
+            let decl_dir = modfile.absolute_path.clone();
+            let externals = modfile.config()?.dep.external.clone();
+
+            for ext in &externals {
+                let source = match vendored(root, ext, &old_lock) {
+                    Some(info) => Source::Vendor {
+                        dir: fs::canonicalize(vendor_dir(root, &ext.name))?,
+                        commit: info.commit,
+                    },
+                    None => Source::of(ext, &decl_dir)?,
+                };
+                let dir = if let Some((prev, dir)) = resolved.get(&ext.name) {
+                    if *prev != source {
+                        return Err(format!(
+                            "External module '{}' is declared with conflicting sources: {} and {}",
+                            ext.name, prev, source
+                        )
+                        .into());
+                    }
+                    dir.clone()
+                } else {
+                    if self.project_map.indices.contains_key(&ext.name) {
+                        return Err(format!(
+                            "External module '{}' conflicts with a project module of the same name",
+                            ext.name
+                        )
+                        .into());
+                    }
+                    let (dir, commit, manifest) = match &source {
+                        Source::Path(dir) => (dir.clone(), None, None),
+                        Source::Vendor { dir, commit } => (dir.clone(), commit.clone(), None),
+                        Source::Archive { path, sha256 } => {
+                            let dir = root.join(".hk").join("deps").join(&ext.name);
+                            let manifest =
+                                unpack_external(&ext.name, path, sha256.as_deref(), &dir)?;
+                            (fs::canonicalize(&dir)?, None, Some(manifest))
+                        }
+                        Source::Git { url, rev } => {
+                            let dir = root.join(".hk").join("deps").join(&ext.name);
+                            let locked = old_lock.find(&ext.name, url, rev.as_deref());
+                            let commit = checkout_git(
+                                &ext.name,
+                                &clone_url(url, &decl_dir)?,
+                                rev.as_deref(),
+                                &dir,
+                                locked,
+                                env.offline,
+                            )?;
+                            (fs::canonicalize(&dir)?, Some(commit), None)
+                        }
+                    };
+                    // vendor 副本同样保留锁定的提交
+                    if let (Some(url), Some(commit)) = (&ext.git, &commit) {
+                        lock.package.push(LockedDep {
+                            name: ext.name.clone(),
+                            git: url.clone(),
+                            rev: ext.rev.clone(),
+                            commit: commit.clone(),
+                        });
+                    }
+
+                    let mut external = ModFile::new();
+                    external.load(&dir, env).map_err(|e| {
+                        format!(
+                            "Failed to load external module '{}' from {}: {}",
+                            ext.name,
+                            dir.display(),
+                            e
+                        )
+                    })?;
+                    if external.name != ext.name {
+                        return Err(format!(
+                            "External module at {} is named '{}', expected '{}'",
+                            dir.display(),
+                            external.name,
+                            ext.name
+                        )
+                        .into());
+                    }
+                    // 归档中的预编译产物与当前目标、配置档一致时跳过编译
+                    let target = external.config()?.target(env).to_string();
+                    external.prebuilt = manifest.is_some_and(|m| m.prebuilt_for(&target, env));
+                    logi!("External module {} at {:?}", ext.name, dir);
+                    self.project_map.mod_names.push(ext.name.clone());
+                    self.project_map.index.insert(ext.name.clone(), dir.clone());
+                    self.project_map.indices.insert(ext.name.clone(), external);
+                    pending.push(ext.name.clone());
+                    resolved.insert(ext.name.clone(), (source, dir.clone()));
+                    self.externals.push(ResolvedExternal {
+                        spec: ext.clone(),
+                        dir: dir.clone(),
+                        commit,
+                    });
+                    dir
+                };
+
+                if let Some(modfile) = self.project_map.indices.get_mut(&name) {
+                    modfile.add_external(&ext.name, &dir);
+                }
+            }
+        }
+
+        lock.package.sort_by(|a, b| a.name.cmp(&b.name));
+        if lock != old_lock && !env.dry_run {
+            lock.save(root)?;
+        }
+        Ok(())
+    }
+
+    /// 将全部外部依赖复制到 vendor/<name>/（不含 .git 与 build/），并删除不再使用的副本，
+    /// 返回复制的模块名
+    pub fn vendor(&self, root: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+        let vendor_root = root.join("vendor");
+        fs::create_dir_all(&vendor_root)?;
+        let vendor_root = fs::canonicalize(&vendor_root)?;
+        let mut vendored = Vec::new();
+
+        // 副本换了位置，先检查所有模块，避免只复制了一部分
+        for ext in &self.externals {
+            check_portable_paths(&ext.spec.name, &ext.dir)?;
+        }
+
+        for ext in &self.externals {
+            let dest = vendor_root.join(&ext.spec.name);
+            if ext.dir != dest {
+                if dest.exists() {
+                    fs::remove_dir_all(&dest)?;
+                }
+                copy_module(&ext.dir, &dest)?;
+                let info = VendorInfo {
+                    source: ext.spec.clone(),
+                    commit: ext.commit.clone(),
+                };
+                fs::write(dest.join(VENDOR_INFO), serde_yaml::to_string(&info)?)?;
+                logi!("Vendored {} from {:?}", ext.spec.name, ext.dir);
+            }
+            vendored.push(ext.spec.name.clone());
+        }
+
+        // 删除 hk vendor 生成但已不再依赖的副本，其他目录保持不动
+        for entry in fs::read_dir(&vendor_root)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if path.join(VENDOR_INFO).exists() && !vendored.iter().any(|v| v == name) {
+                logi!("Removing stale vendored module {:?}", path);
+                fs::remove_dir_all(&path)?;
+            }
+        }
+        Ok(vendored)
+    }
+}
+
+/// 检查模块配置中的相对路径都位于模块目录内，否则复制到 vendor/ 后会指向别处
+fn check_portable_paths(name: &str, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let config = Config::from_yaml(&dir.join("config.yaml"))
+        .map_err(|e| format!("Failed to read config of external module '{}': {}", name, e))?;
+    let mut paths: Vec<(&str, &str)> = Vec::new();
+    paths.extend(
+        config
+            .dep
+            .include
+            .iter()
+            .map(|p| ("dep.include", p.as_str())),
+    );
+    paths.extend(
+        config
+            .dep
+            .mod_deps
+            .iter()
+            .map(|d| ("dep.mod_deps", d.path.as_str())),
+    );
+    for ext in &config.dep.external {
+        paths.extend(ext.path.as_deref().map(|p| ("dep.external.path", p)));
+        paths.extend(ext.archive.as_deref().map(|p| ("dep.external.archive", p)));
+    }
+    if let Some(fw) = &config.firmware {
+        paths.extend(
+            fw.linker_script
+                .as_deref()
+                .map(|p| ("firmware.linker_script", p)),
+        );
+        paths.extend(fw.startup.iter().map(|p| ("firmware.startup", p.as_str())));
+    }
+    for block in &config.conditional {
+        paths.extend(
+            block
+                .include
+                .iter()
+                .map(|p| ("conditional.include", p.as_str())),
+        );
+    }
+    let flags = config
+        .compiler
+        .flags
+        .iter()
+        .chain(config.conditional.iter().flat_map(|c| &c.flags));
+    paths.extend(
+        flags
+            .filter_map(|f| f.strip_prefix("-I"))
+            .map(|p| ("compiler.flags", p)),
+    );
+
+    for (field, path) in paths {
+        if !stays_inside(Path::new(path)) {
+            return Err(format!(
+                "Cannot vendor external module '{}': {} '{}' points outside the module directory; \
+                 use an absolute path or declare it as an external dependency",
+                name, field, path
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// 相对路径按字面解析后是否仍在起始目录内，绝对路径不受复制位置影响
+fn stays_inside(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return true,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            },
+            Component::Normal(_) => depth += 1,
+        }
+    }
+    true
+}
+
+/// 复制模块目录，跳过版本库与构建产物
+fn copy_module(src: &Path, dest: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default();
+        if name == ".git" || name == ".hk" || name == "build" {
+            continue;
+        }
+        let target = dest.join(name);
+        if path.is_dir() {
+            copy_module(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hk-external-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run_git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "hk")
+            .env("GIT_AUTHOR_EMAIL", "hk@example.com")
+            .env("GIT_COMMITTER_NAME", "hk")
+            .env("GIT_COMMITTER_EMAIL", "hk@example.com")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {:?}",
+            args,
+            output
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    fn module_config(name: &str, external: &str) -> String {
+        format!(
+            "name: {}\nversion: 0.1.0\nstd: c99\npremacro: []\n\
+             dep:\n  include: []\n  mod_deps: []\n  lib: []\n{}compiler:\n  cc: gcc\n",
+            name, external
+        )
+    }
+
+    /// 提交工作区的全部改动并推送当前分支，返回提交哈希
+    fn commit(work: &Path, message: &str) -> String {
+        fs::write(work.join("notes.txt"), message).unwrap();
+        run_git(work, &["add", "-A"]);
+        run_git(work, &["commit", "--quiet", "-m", message]);
+        run_git(work, &["push", "--quiet", "origin", "HEAD"]);
+        run_git(work, &["rev-parse", "HEAD"])
+    }
+
+    /// 本地裸仓库 upstream.git 中的外部模块 ext：
+    /// main 上两个提交（第一个打了标签 v1），dev 分支从 v1 分出一个提交
+    struct Upstream {
+        url: String,
+        work: PathBuf,
+        tagged: String,
+        main: String,
+        dev: String,
+    }
+
+    fn upstream(root: &Path) -> Upstream {
+        let bare = root.join("upstream.git");
+        let work = root.join("work");
+        fs::create_dir_all(&bare).unwrap();
+        fs::create_dir_all(&work).unwrap();
+        run_git(&bare, &["init", "--quiet", "--bare", "-b", "main"]);
+        run_git(&work, &["init", "--quiet", "-b", "main"]);
+        run_git(
+            &work,
+            &["remote", "add", "origin", &bare.display().to_string()],
+        );
+        fs::write(work.join("config.yaml"), module_config("ext", "")).unwrap();
+
+        let tagged = commit(&work, "first");
+        run_git(&work, &["tag", "v1"]);
+        run_git(&work, &["push", "--quiet", "origin", "v1"]);
+        let main = commit(&work, "second");
+        run_git(&work, &["checkout", "--quiet", "-b", "dev", "v1"]);
+        let dev = commit(&work, "dev");
+        run_git(&work, &["checkout", "--quiet", "main"]);
+        Upstream {
+            url: bare.display().to_string(),
+            work,
+            tagged,
+            main,
+            dev,
+        }
+    }
+
+    #[test]
+    fn checkout_git_resolves_tag_branch_and_rev() {
+        let root = temp_dir("checkout");
+        let up = upstream(&root);
+        let cases = [
+            ("tag", Some("v1"), &up.tagged),
+            ("branch", Some("dev"), &up.dev),
+            ("rev", Some(up.main.as_str()), &up.main),
+            ("default", None, &up.main),
+        ];
+        for (dir_name, rev, expected) in cases {
+            let dir = root.join("deps").join(dir_name);
+            let commit = checkout_git("ext", &up.url, rev, &dir, None, false).unwrap();
+            assert_eq!(&commit, expected, "rev {:?}", rev);
+            assert_eq!(git::head(&dir).as_ref(), Some(expected), "rev {:?}", rev);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn lock_file_pins_git_commit() {
+        let root = temp_dir("lock");
+        let up = upstream(&root);
+        let project = root.join("project");
+        let app = project.join("app");
+        fs::create_dir_all(&app).unwrap();
+        let external = format!(
+            "  external:\n  - name: ext\n    git: {}\n    rev: main\n",
+            up.url
+        );
+        fs::write(app.join("config.yaml"), module_config("app", &external)).unwrap();
+
+        let resolve = || {
+            let mut mgr = ModMgr::new();
+            mgr.build_dep_graph(&project, &BuildEnv::default()).unwrap();
+            mgr.externals[0].commit.clone().unwrap()
+        };
+        assert_eq!(resolve(), up.main);
+        let lock = LockFile::load(&project).unwrap();
+        assert_eq!(lock.package.len(), 1);
+        assert_eq!(lock.package[0].commit, up.main);
+
+        // 上游前进后仍使用锁定的提交，删除锁文件才会更新
+        let newer = commit(&up.work, "third");
+        assert_eq!(resolve(), up.main);
+        assert_eq!(
+            git::head(&project.join(".hk/deps/ext")),
+            Some(up.main.clone())
+        );
+        fs::remove_file(LockFile::path(&project)).unwrap();
+        assert_eq!(resolve(), newer);
+        assert_eq!(LockFile::load(&project).unwrap().package[0].commit, newer);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn vendored_copy_matches_source_not_whole_declaration() {
+        let root = temp_dir("vendor");
+        let up = upstream(&root);
+        let project = root.join("project");
+        let app = project.join("app");
+        fs::create_dir_all(&app).unwrap();
+        let declare = |extra: &str| {
+            let external = format!(
+                "  external:\n  - name: ext\n    git: {}\n    rev: main\n{}",
+                up.url, extra
+            );
+            fs::write(app.join("config.yaml"), module_config("app", &external)).unwrap();
+        };
+        let resolve = |offline: bool| {
+            let env = BuildEnv {
+                offline,
+                ..BuildEnv::default()
+            };
+            let mut mgr = ModMgr::new();
+            mgr.build_dep_graph(&project, &env).map(|_| mgr)
+        };
+
+        declare("");
+        resolve(false).unwrap().vendor(&project).unwrap();
+        fs::remove_dir_all(project.join(".hk")).unwrap();
+
+        // 只改版本要求时仍使用 vendor 副本，离线也能解析
+        declare("    version: \">=0.1\"\n");
+        let mgr = resolve(true).unwrap();
+        let vendor = fs::canonicalize(vendor_dir(&project, "ext")).unwrap();
+        assert_eq!(mgr.externals[0].dir, vendor);
+        assert_eq!(mgr.externals[0].commit.as_ref(), Some(&up.main));
+
+        // 仓库地址改变后不再使用副本
+        let moved = root.join("moved.git");
+        fs::rename(&up.url, &moved).unwrap();
+        let external = format!(
+            "  external:\n  - name: ext\n    git: {}\n    rev: main\n",
+            moved.display()
+        );
+        fs::write(app.join("config.yaml"), module_config("app", &external)).unwrap();
+        assert!(resolve(true).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn offline_fails_without_checkout() {
+        let root = temp_dir("offline");
+        let up = upstream(&root);
+        let dir = root.join("deps").join("ext");
+        let err = checkout_git("ext", &up.url, Some("main"), &dir, None, true).unwrap_err();
+        assert!(err.to_string().contains("--offline"), "{}", err);
+        assert!(!dir.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn git_options_in_url_and_rev_are_rejected() {
+        let root = temp_dir("options");
+        let marker = root.join("pwned");
+        let payload = format!("--upload-pack=touch {}", marker.display());
+
+        assert!(clone_url(&payload, &root).is_err());
+        assert!(clone_url("ext::sh -c touch% /tmp/pwned", &root).is_err());
+        // 绕过 clone_url 直接克隆时，-- 使地址不会被当作选项
+        assert!(git::clone(&payload, &root.join("clone")).is_err());
+        assert!(!marker.exists());
+
+        let up = upstream(&root);
+        let dir = root.join("deps").join("ext");
+        let rev = format!("--output={}", marker.display());
+        let err = checkout_git("ext", &up.url, Some(&rev), &dir, None, false).unwrap_err();
+        assert!(err.to_string().contains("Invalid git revision"), "{}", err);
+        assert!(!dir.exists());
+        assert!(!marker.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn vendor_refuses_paths_outside_the_module() {
+        let root = temp_dir("portable");
+        let dir = root.join("ext");
+        fs::create_dir_all(&dir).unwrap();
+        let config = |include: &str| {
+            module_config("ext", "").replace("  include: []", &format!("  include: [{}]", include))
+        };
+
+        fs::write(
+            dir.join("config.yaml"),
+            config("include/extra, ./gen/../gen, /opt/inc"),
+        )
+        .unwrap();
+        check_portable_paths("ext", &dir).unwrap();
+
+        fs::write(dir.join("config.yaml"), config("../shared/include")).unwrap();
+        let err = check_portable_paths("ext", &dir).unwrap_err();
+        assert!(
+            err.to_string().contains("dep.include '../shared/include'"),
+            "{}",
+            err
+        );
+
+        fs::write(dir.join("config.yaml"), config("gen/../../shared")).unwrap();
+        assert!(check_portable_paths("ext", &dir).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use crate::logi;
+use crate::module::{export::PackageInfo, mod_file::ModFile};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 安装目标：前缀与 DESTDIR 暂存目录
+#[derive(Debug, Clone)]
+pub struct InstallDirs {
+    /// 安装前缀，如 /usr/local，写入 .pc 文件
+    pub prefix: PathBuf,
+    /// 实际写入的根目录：DESTDIR + prefix
+    pub stage: PathBuf,
+}
+
+impl InstallDirs {
+    /// 由前缀与 DESTDIR 计算安装目录，DESTDIR 为空时直接安装到前缀
+    pub fn new(prefix: &Path, destdir: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        if !prefix.is_absolute() {
+            return Err(format!("Install prefix must be absolute: {}", prefix.display()).into());
+        }
+        let stage = match destdir {
+            Some(dest) if !dest.as_os_str().is_empty() => {
+                dest.join(prefix.strip_prefix("/").unwrap_or(prefix))
+            }
+            _ => prefix.to_path_buf(),
+        };
+        Ok(InstallDirs {
+            prefix: prefix.to_path_buf(),
+            stage,
+        })
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.stage.join("bin")
+    }
+
+    pub fn lib(&self) -> PathBuf {
+        self.stage.join("lib")
+    }
+
+    pub fn include(&self) -> PathBuf {
+        self.stage.join("include")
+    }
+
+    pub fn pkgconfig(&self) -> PathBuf {
+        self.lib().join("pkgconfig")
+    }
+
+    pub fn cmake(&self, name: &str) -> PathBuf {
+        self.lib().join("cmake").join(name)
+    }
+}
+
+impl ModFile {
+    /// 安装模块产物：可执行文件、静态库、include/ 与生成的头文件以及库的 pkg-config 与 CMake 包文件，
+    /// 返回所有写入的文件路径，用于生成安装清单
+    pub fn install(
+        &self,
+        dirs: &InstallDirs,
+        package: Option<&PackageInfo>,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut installed = Vec::new();
+
+        for exe in self.executables()? {
+            installed.push(install_file(&exe, &dirs.bin())?);
+        }
+
+        if let Some(info) = package {
+            installed.push(install_file(&info.lib_file, &dirs.lib())?);
+
+            let pc = dirs.pkgconfig().join(format!("{}.pc", self.name));
+            installed.push(write_file(&pc, &info.installed_pc(&dirs.prefix))?);
+
+            let cmake = dirs
+                .cmake(&self.name)
+                .join(format!("{}Config.cmake", self.name));
+            installed.push(write_file(&cmake, &info.installed_cmake())?);
+        }
+
+        for (src, dest) in self.install_headers(dirs)? {
+            installed.push(install_file(&src, dest.parent().unwrap())?);
+        }
+        Ok(installed)
+    }
+
+    /// 要安装的头文件：模块 include/ 与生成的头文件（如 <name>_version.h），
+    /// 返回 (源文件, 安装路径)，目录结构保持不变
+    pub fn install_headers(
+        &self,
+        dirs: &InstallDirs,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+        let mut headers = Vec::new();
+        for include_dir in [
+            self.absolute_path.join("include"),
+            self.absolute_path.join("build").join("include"),
+        ] {
+            if include_dir.is_dir() {
+                list_tree(&include_dir, &dirs.include(), &mut headers)?;
+            }
+        }
+        Ok(headers)
+    }
+}
+
+/// 检查各模块安装的头文件是否同名冲突，modules 为 (模块名, 头文件列表)
+pub fn check_header_conflicts(
+    modules: &[(String, Vec<(PathBuf, PathBuf)>)],
+) -> Result<(), Box<dyn Error>> {
+    let mut owners: HashMap<&Path, &str> = HashMap::new();
+    for (name, headers) in modules {
+        for (_, dest) in headers {
+            if let Some(owner) = owners.insert(dest, name) {
+                return Err(format!(
+                    "Header {} is installed by both {} and {}; move one into an include/<module>/ subdirectory",
+                    dest.display(),
+                    owner,
+                    name
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 写入生成的文件
+fn write_file(path: &Path, content: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)?;
+    logi!("Installed: {:?}", path);
+    Ok(path.to_path_buf())
+}
+
+/// 复制单个文件到目录，保留权限
+fn install_file(src: &Path, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let dest = dir.join(
+        src.file_name()
+            .ok_or_else(|| format!("Invalid file name: {}", src.display()))?,
+    );
+    fs::copy(src, &dest)?;
+    logi!("Installed: {:?}", dest);
+    Ok(dest)
+}
+
+/// 递归列出目录树中的文件及其安装路径
+fn list_tree(
+    src: &Path,
+    dest: &Path,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(src)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        let target = dest.join(path.file_name().unwrap());
+        if path.is_dir() {
+            list_tree(&path, &target, files)?;
+        } else {
+            files.push((path, target));
+        }
+    }
+    Ok(())
+}
+
+/// 安装布局中的标准目录，卸载时不删除
+const STANDARD_DIRS: [&str; 5] = ["bin", "lib", "include", "pkgconfig", "cmake"];
+
+/// 安装清单：记录安装的全部文件，供 hk uninstall 反向删除
+pub struct InstallManifest;
+
+impl InstallManifest {
+    /// 清单位置：<project>/.hk/install_manifest.txt
+    pub fn path(project_root: &Path) -> PathBuf {
+        project_root.join(".hk").join("install_manifest.txt")
+    }
+
+    /// 写入清单，与已有清单合并去重
+    pub fn write(project_root: &Path, files: &[PathBuf]) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::path(project_root);
+        let mut all = Self::read(project_root).unwrap_or_default();
+        for file in files {
+            if !all.contains(file) {
+                all.push(file.clone());
+            }
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: Vec<String> = all.iter().map(|p| p.display().to_string()).collect();
+        fs::write(&path, text.join("\n") + "\n")?;
+        Ok(path)
+    }
+
+    pub fn read(project_root: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let path = Self::path(project_root);
+        let text = fs::read_to_string(&path)
+            .map_err(|_| format!("No install manifest found at {}", path.display()))?;
+        Ok(text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    /// 按清单删除已安装文件并清理变空的目录，最后删除清单
+    pub fn uninstall(project_root: &Path) -> Result<usize, Box<dyn Error>> {
+        let files = Self::read(project_root)?;
+        let mut removed = 0;
+        for file in &files {
+            if file.exists() {
+                fs::remove_file(file)?;
+                logi!("Removed: {:?}", file);
+                removed += 1;
+            }
+            // 向上删除安装时创建的空子目录（如 include/net/），标准布局目录保留
+            let mut dir = file.parent();
+            while let Some(d) = dir {
+                let standard = d
+                    .file_name()
+                    .is_some_and(|n| STANDARD_DIRS.iter().any(|s| n == *s));
+                if standard || fs::remove_dir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+        fs::remove_file(Self::path(project_root))?;
+        Ok(removed)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::module::env::BuildEnv;
+use crate::module::mod_file::ModFile;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 静态分析工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintTool {
+    ClangTidy,
+    Cppcheck,
+}
+
+impl LintTool {
+    pub const ALL: [LintTool; 2] = [LintTool::ClangTidy, LintTool::Cppcheck];
+
+    pub fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value {
+            "clang-tidy" => Ok(LintTool::ClangTidy),
+            "cppcheck" => Ok(LintTool::Cppcheck),
+            _ => Err(format!(
+                "Unknown lint tool: {} (expected clang-tidy or cppcheck)",
+                value
+            )
+            .into()),
+        }
+    }
+
+    /// 可执行文件名
+    pub fn program(&self) -> &'static str {
+        match self {
+            LintTool::ClangTidy => "clang-tidy",
+            LintTool::Cppcheck => "cppcheck",
+        }
+    }
+}
+
+impl ModFile {
+    /// 需要分析的源文件：src/、bin/ 与 test/ 下的 .c 文件
+    pub fn lint_sources(&self) -> Vec<PathBuf> {
+        [&self.lib_sources, &self.bin_sources, &self.test_sources]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|p| p.extension().is_some_and(|ext| ext == "c"))
+            .cloned()
+            .collect()
+    }
+
+    /// 与编译时相同的参数（不含 -c、-o 与优化等级），并加入额外 flags 中的
+    /// -D、-U、-I 与 -include
+    fn lint_args(&self, env: &BuildEnv) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut args = self.compile_args(env)?;
+        let mut flags = self.config()?.compiler.flags.iter();
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "-D" | "-U" | "-I" | "-include" => {
+                    args.push(flag.clone());
+                    args.extend(flags.next().cloned());
+                }
+                _ if ["-D", "-U", "-I"].iter().any(|p| flag.starts_with(p)) => {
+                    args.push(flag.clone())
+                }
+                _ => {}
+            }
+        }
+        Ok(args)
+    }
+
+    /// 生成用 tool 分析单个源文件的命令，诊断输出为 `file:line:col: warning: ...` 格式
+    pub fn lint_cmd(
+        &self,
+        env: &BuildEnv,
+        tool: LintTool,
+        source: &Path,
+    ) -> Result<Command, Box<dyn Error>> {
+        let args = self.lint_args(env)?;
+        let mut cmd = Command::new(tool.program());
+        match tool {
+            LintTool::ClangTidy => {
+                cmd.arg("--quiet").arg(source).arg("--").args(args);
+            }
+            LintTool::Cppcheck => {
+                cmd.args([
+                    "--quiet",
+                    "--inline-suppr",
+                    "--enable=warning,style,performance,portability",
+                    "--template={file}:{line}:{column}: warning: {message} [{severity}/{id}]",
+                ]);
+                // cppcheck 只认 --std=c99 这类标准名与 --include=，-D、-U 与 -I 与编译器相同，
+                // 其余参数（目标、sysroot、pkg-config 的其他参数）忽略
+                let mut args = args.into_iter();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-D" | "-U" | "-I" => {
+                            cmd.arg(arg).args(args.next());
+                        }
+                        "-include" => {
+                            if let Some(file) = args.next() {
+                                cmd.arg(format!("--include={}", file));
+                            }
+                        }
+                        _ => {
+                            if let Some(std) = arg.strip_prefix("-std=") {
+                                cmd.arg(format!("--std={}", std.replace("gnu", "c")));
+                            } else if ["-D", "-U", "-I"].iter().any(|p| arg.starts_with(p)) {
+                                cmd.arg(arg);
+                            }
+                        }
+                    }
+                }
+                cmd.arg(source);
+            }
+        }
+        Ok(cmd)
+    }
+}
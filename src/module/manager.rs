@@ -1,18 +1,27 @@
-use crate::{logd, loge, logi};
+use crate::logi;
 use crate::{
+    module::env::BuildEnv,
+    module::external::ResolvedExternal,
     module::mod_file::{ModFile, ProjectMap},
-    utils::yaml::{Config, Dep},
 };
 use petgraph::{
+    algo::has_path_connecting,
     graph::{DiGraph, NodeIndex},
-    visit::IntoNodeIdentifiers,
+    visit::{Dfs, IntoNodeIdentifiers, Reversed},
+};
+use semver::{Version, VersionReq};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
 };
-use std::{collections::HashMap, error::Error, path::PathBuf};
 
 #[derive(Debug)]
 pub struct ModMgr {
     pub graph: DiGraph<String, ()>,
     pub project_map: ProjectMap,
+    /// 已解析的外部依赖模块
+    pub externals: Vec<ResolvedExternal>,
 }
 
 impl ModMgr {
@@ -20,16 +29,20 @@ impl ModMgr {
         ModMgr {
             graph: DiGraph::new(),
             project_map: ProjectMap::new(),
+            externals: Vec::new(),
         }
     }
 
     ///构建mods之间的关联图
-    pub fn build_dep_graph(&mut self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    pub fn build_dep_graph(&mut self, path: &Path, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
         logi!("build_dep_graph path:{:#?}", path);
         let mut indices = HashMap::new();
 
         //1.获取工具运行目录的现有模块信息
-        self.project_map.discover_mods(path)?;
+        self.project_map.discover_mods(path, env)?;
+
+        //2.解析外部依赖模块并加入项目
+        self.resolve_externals(path, env)?;
 
         //添加节点
         for modname in &self.project_map.mod_names {
@@ -44,14 +57,175 @@ impl ModMgr {
 
             //变量指向此mod的其他mod，添加到图中
             let cfg = self.project_map.indices[modname].config()?;
-            let mod_deps = &cfg.dep.mod_deps;
-            for depmod in mod_deps {
-                let dep_mod_clean = Dep::clean_mod_name(depmod);
-                if let Some(&dep_idx) = indices.get(dep_mod_clean.as_str()) {
+            for dep_name in cfg.dep.dep_names() {
+                if let Some(&dep_idx) = indices.get(dep_name.as_str()) {
                     self.graph.add_edge(dep_idx, cur_idx, ());
                 }
             }
         }
+
+        //3.校验模块版本与依赖的版本要求
+        self.check_versions()
+    }
+
+    ///模块自身及其直接、间接依赖的全部模块名
+    pub fn with_deps(&self, name: &str) -> HashSet<String> {
+        let mut names = HashSet::new();
+        let Some(start) = self.graph.node_indices().find(|&i| self.graph[i] == name) else {
+            return names;
+        };
+        // 依赖边由被依赖方指向依赖方，沿反向边遍历得到所有依赖
+        let reversed = Reversed(&self.graph);
+        let mut dfs = Dfs::new(reversed, start);
+        while let Some(idx) = dfs.next(reversed) {
+            names.insert(self.graph[idx].clone());
+        }
+        names
+    }
+
+    ///校验能否为模块 from 新增对 to 的依赖：to 须为项目内模块、尚未依赖且不会形成环
+    pub fn check_new_dep(&self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        let map = &self.project_map;
+        if !map.indices.contains_key(to) {
+            let mut names: Vec<&str> = map.mod_names.iter().map(String::as_str).collect();
+            names.sort();
+            return Err(format!(
+                "No module named '{}' in the project, available: {}",
+                to,
+                names.join(", ")
+            )
+            .into());
+        }
+        if self.externals.iter().any(|e| e.spec.name == to) {
+            return Err(format!(
+                "'{}' is an external module, declare it under dep.external instead",
+                to
+            )
+            .into());
+        }
+        if from == to {
+            return Err(format!("Module '{}' cannot depend on itself", to).into());
+        }
+        if map.indices[from]
+            .config()?
+            .dep
+            .dep_names()
+            .iter()
+            .any(|n| n == to)
+        {
+            return Err(format!("Module '{}' already depends on '{}'", from, to).into());
+        }
+
+        // 依赖边由被依赖方指向依赖方，to 已经（间接）依赖 from 时新增依赖会成环
+        let node = |name: &str| {
+            self.graph
+                .node_indices()
+                .find(|&i| self.graph[i] == name)
+                .ok_or_else(|| format!("Module '{}' is not in the dependency graph", name))
+        };
+        if has_path_connecting(&self.graph, node(from)?, node(to)?, None) {
+            return Err(format!(
+                "Adding '{}' to '{}' would create a dependency cycle: '{}' already depends on '{}'",
+                to, from, to, from
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    ///校验各模块的版本号，以及依赖方的版本要求是否都能满足，
+    ///不满足时列出该模块的全部要求方便定位冲突
+    fn check_versions(&self) -> Result<(), Box<dyn Error>> {
+        let map = &self.project_map;
+        let mut versions = HashMap::new();
+        for name in &map.mod_names {
+            let version = &map.indices[name].config()?.version;
+            let parsed = Version::parse(version).map_err(|e| {
+                format!("Module '{}' has invalid version '{}': {}", name, version, e)
+            })?;
+            versions.insert(name.clone(), parsed);
+        }
+
+        // 依赖模块名 → (依赖方, 版本要求, 是否满足)
+        let mut requirements: BTreeMap<String, Vec<(String, String, bool)>> = BTreeMap::new();
+        for name in &map.mod_names {
+            let cfg = map.indices[name].config()?;
+            for (dep, req) in cfg.dep.dep_requirements() {
+                let (Some(req), Some(version)) = (req, versions.get(&dep)) else {
+                    continue;
+                };
+                let parsed = VersionReq::parse(req).map_err(|e| {
+                    format!(
+                        "Module '{}' has invalid version requirement '{}' on '{}': {}",
+                        name, req, dep, e
+                    )
+                })?;
+                requirements.entry(dep).or_default().push((
+                    name.clone(),
+                    req.to_string(),
+                    parsed.matches(version),
+                ));
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        for (dep, reqs) in &requirements {
+            if reqs.iter().all(|(_, _, ok)| *ok) {
+                continue;
+            }
+            let mut msg = format!("Version conflict for module '{}' {}:", dep, versions[dep]);
+            for (user, req, ok) in reqs {
+                msg.push_str(&format!(
+                    "\n  {} requires {}{}",
+                    user,
+                    req,
+                    if *ok { "" } else { "  (not satisfied)" }
+                ));
+            }
+            conflicts.push(msg);
+        }
+        if !conflicts.is_empty() {
+            return Err(conflicts.join("\n").into());
+        }
+        Ok(())
+    }
+
+    ///配置所有模块（解析 pkg-config 依赖），在构建任何模块前尽早失败
+    pub fn configure(&mut self, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        for name in &self.project_map.mod_names {
+            if let Some(modfile) = self.project_map.indices.get_mut(name) {
+                modfile.configure(env)?;
+            }
+        }
+        self.propagate_link_deps()
+    }
+
+    ///把依赖模块（含间接依赖）的静态库与 pkg-config 链接参数传给依赖它们的模块，静态库不携带这些依赖
+    fn propagate_link_deps(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut link_deps = Vec::new();
+        for name in &self.project_map.mod_names {
+            // 依赖的拓扑序反转后即为链接顺序
+            let mut order = Vec::new();
+            self.visit_deps(name, &mut HashSet::new(), &mut order)?;
+            order.retain(|n| n != name);
+            order.reverse();
+            let indices = &self.project_map.indices;
+            let roots: Vec<(String, PathBuf)> = order
+                .iter()
+                .map(|dep| (dep.clone(), indices[dep].absolute_path.clone()))
+                .collect();
+            let libs: Vec<String> = order
+                .iter()
+                .flat_map(|dep| indices[dep].pkg_flags.libs.iter().cloned())
+                .collect();
+            link_deps.push((name.clone(), roots, libs));
+        }
+        for (name, roots, libs) in link_deps {
+            if let Some(modfile) = self.project_map.indices.get_mut(&name) {
+                modfile.dep_lib_roots = roots;
+                modfile.dep_pkg_libs = libs;
+            }
+        }
         Ok(())
     }
 
@@ -63,13 +237,10 @@ impl ModMgr {
         let build_list = Self::find_src_nodes(self)?;
 
         for node_id in &build_list {
-            match self.graph.node_weight(*node_id) {
-                Some(name) => {
-                    if let Some(modfile) = self.project_map.indices.get(name) {
-                        next_build.push(modfile.clone());
-                    }
+            if let Some(name) = self.graph.node_weight(*node_id) {
+                if let Some(modfile) = self.project_map.indices.get(name) {
+                    next_build.push(modfile.clone());
                 }
-                None => {}
             }
         }
 
@@ -101,9 +272,114 @@ impl ModMgr {
     }
 
     ///删除节点
-    fn remove_nodes(&mut self, list: Vec<NodeIndex>) {
+    fn remove_nodes(&mut self, mut list: Vec<NodeIndex>) {
+        // remove_node 会把最后一个节点移到被删位置，需从大到小删除以免索引失效
+        list.sort_by_key(|n| std::cmp::Reverse(n.index()));
         for node in list {
             self.graph.remove_node(node);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// 在临时目录下创建项目，每个模块为 (名字, 直接依赖)
+    fn project(name: &str, mods: &[(&str, &[&str])]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("hk-manager-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (mod_name, deps) in mods {
+            let dir = root.join(mod_name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("config.yaml"),
+                format!(
+                    "name: {}\nversion: 0.1.0\nstd: c99\npremacro: []\n\
+                     dep:\n  include: []\n  mod_deps: [{}]\n  lib: []\ncompiler:\n  cc: gcc\n",
+                    mod_name,
+                    deps.join(", ")
+                ),
+            )
+            .unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn buildable_rounds_follow_dependencies() {
+        let root = project(
+            "rounds",
+            &[
+                ("app", &["base", "util"]),
+                ("base", &[]),
+                ("tool", &["base"]),
+                ("util", &[]),
+                ("zlib", &[]),
+            ],
+        );
+        let mut mgr = ModMgr::new();
+        mgr.build_dep_graph(&root, &BuildEnv::default()).unwrap();
+
+        let mut built: Vec<String> = Vec::new();
+        loop {
+            let next = mgr.get_next_buildable().unwrap();
+            if next.is_empty() {
+                break;
+            }
+            for modfile in next {
+                for dep in modfile.config().unwrap().dep.dep_names() {
+                    assert!(
+                        built.contains(&dep),
+                        "{} built before {}",
+                        modfile.name,
+                        dep
+                    );
+                }
+                built.push(modfile.name);
+            }
+        }
+        built.sort();
+        assert_eq!(built, ["app", "base", "tool", "util", "zlib"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn link_deps_propagate_to_indirect_dependents() {
+        let root = project(
+            "pkg-libs",
+            &[("app", &["net"]), ("net", &["base"]), ("base", &[])],
+        );
+        let mut mgr = ModMgr::new();
+        mgr.build_dep_graph(&root, &BuildEnv::default()).unwrap();
+        let indices = &mut mgr.project_map.indices;
+        indices.get_mut("net").unwrap().pkg_flags.libs = vec!["-lssl".into()];
+        indices.get_mut("base").unwrap().pkg_flags.libs = vec!["-lz".into()];
+
+        mgr.propagate_link_deps().unwrap();
+
+        let indices = &mgr.project_map.indices;
+        assert_eq!(indices["app"].dep_pkg_libs, ["-lssl", "-lz"]);
+        assert_eq!(indices["net"].dep_pkg_libs, ["-lz"]);
+        assert!(indices["base"].dep_pkg_libs.is_empty());
+
+        // 静态库同样按链接顺序传递：app 链接 libnet.a 后还需 libbase.a
+        let names = |m: &str| -> Vec<String> {
+            indices[m]
+                .dep_lib_roots
+                .iter()
+                .map(|(n, _)| n.clone())
+                .collect()
+        };
+        assert_eq!(names("app"), ["net", "base"]);
+        assert_eq!(names("net"), ["base"]);
+        assert!(names("base").is_empty());
+        assert_eq!(
+            indices["app"].dep_lib_roots[1].1,
+            root.join("base").canonicalize().unwrap()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use crate::module::{env::BuildEnv, manager::ModMgr, mod_file::ModFile};
+use crate::utils::yaml::Compiler;
+use petgraph::algo::toposort;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 元数据格式版本，字段有不兼容变化时递增
+pub const METADATA_VERSION: u32 = 1;
+
+/// hk metadata 输出的项目元数据
+#[derive(Debug, Serialize)]
+pub struct ProjectMetadata {
+    pub version: u32,
+    pub root: PathBuf,
+    pub host: String,
+    pub profile: &'static str,
+    pub toolchain: Option<String>,
+    /// 按名称排序的全部模块（含外部依赖）
+    pub modules: Vec<ModuleMetadata>,
+    /// 依赖在前的构建顺序
+    pub build_order: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleMetadata {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+    /// 外部依赖来源（path / git / archive），项目内模块为空
+    pub external: Option<ExternalMetadata>,
+    pub std: String,
+    pub defines: Vec<String>,
+    pub sources: SourcesMetadata,
+    pub include_paths: Vec<PathBuf>,
+    pub dependencies: Vec<DependencyMetadata>,
+    pub system_libs: Vec<String>,
+    pub pkg_config: Vec<String>,
+    pub compiler: CompilerMetadata,
+    pub firmware: bool,
+    pub prebuilt: bool,
+    pub artifacts: ArtifactsMetadata,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExternalMetadata {
+    pub kind: &'static str,
+    pub source: String,
+    pub rev: Option<String>,
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourcesMetadata {
+    pub lib: Vec<PathBuf>,
+    pub bin: Vec<PathBuf>,
+    pub test: Vec<PathBuf>,
+    pub startup: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyMetadata {
+    pub name: String,
+    pub path: PathBuf,
+    pub requirement: Option<String>,
+    pub external: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompilerMetadata {
+    #[serde(flatten)]
+    pub config: Compiler,
+    /// 探测到的编译器种类与版本
+    pub kind: Option<&'static str>,
+    pub detected_version: Option<String>,
+    pub archiver: String,
+    pub linker_driver: String,
+}
+
+/// 构建产物的预期路径，不要求已经构建
+#[derive(Debug, Serialize)]
+pub struct ArtifactsMetadata {
+    pub library: Option<PathBuf>,
+    pub executables: Vec<PathBuf>,
+    pub tests: Vec<PathBuf>,
+    pub images: Vec<PathBuf>,
+    pub pkg_config: Option<PathBuf>,
+    pub cmake: Option<PathBuf>,
+}
+
+impl ModMgr {
+    /// 汇总项目中所有模块的元数据
+    pub fn metadata(&self, env: &BuildEnv, root: &Path) -> Result<ProjectMetadata, Box<dyn Error>> {
+        let mut names = self.project_map.mod_names.clone();
+        names.sort();
+        let mut modules = Vec::new();
+        for name in &names {
+            modules.push(self.module_metadata(&self.project_map.indices[name], env)?);
+        }
+
+        let order = toposort(&self.graph, None)
+            .map_err(|_| "cur project exit loop include")?
+            .into_iter()
+            .map(|idx| self.graph[idx].clone())
+            .collect();
+
+        Ok(ProjectMetadata {
+            version: METADATA_VERSION,
+            root: fs::canonicalize(root)?,
+            host: env.host.clone(),
+            profile: env.profile.as_str(),
+            toolchain: env.toolchain.as_ref().map(|t| t.name.clone()),
+            modules,
+            build_order: order,
+        })
+    }
+
+    fn module_metadata(
+        &self,
+        modfile: &ModFile,
+        env: &BuildEnv,
+    ) -> Result<ModuleMetadata, Box<dyn Error>> {
+        let config = modfile.config()?;
+        let root = &modfile.absolute_path;
+        let build = &modfile.build_dir;
+
+        let external = self
+            .externals
+            .iter()
+            .find(|e| e.spec.name == modfile.name)
+            .map(|e| {
+                let (kind, source) = match (&e.spec.path, &e.spec.git, &e.spec.archive) {
+                    (Some(path), _, _) => ("path", path.clone()),
+                    (_, Some(git), _) => ("git", git.clone()),
+                    (_, _, Some(archive)) => ("archive", archive.clone()),
+                    _ => ("unknown", String::new()),
+                };
+                ExternalMetadata {
+                    kind,
+                    source,
+                    rev: e.spec.rev.clone(),
+                    commit: e.commit.clone(),
+                }
+            });
+
+        let requirements = config.dep.dep_requirements();
+        let dependencies = modfile
+            .dep_roots()?
+            .into_iter()
+            .map(|(name, path)| {
+                let requirement = requirements
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .and_then(|(_, r)| r.map(str::to_string));
+                DependencyMetadata {
+                    external: modfile.external_roots.iter().any(|(n, _)| *n == name),
+                    path: fs::canonicalize(&path).unwrap_or(path),
+                    name,
+                    requirement,
+                }
+            })
+            .collect();
+
+        let lib_sources = modfile.lib_sources.clone().unwrap_or_default();
+        let bin_sources = modfile.bin_sources.clone().unwrap_or_default();
+        let test_sources = modfile.test_sources.clone().unwrap_or_default();
+        let stem = |p: &PathBuf| p.file_stem().map(PathBuf::from).unwrap_or_default();
+
+        let has_lib = lib_sources.iter().any(|p| {
+            p.extension()
+                .is_some_and(|e| e == "c" || e == "s" || e == "S")
+        });
+        let executables: Vec<PathBuf> = bin_sources
+            .iter()
+            .map(|s| build.join("bin").join(stem(s)))
+            .collect();
+        let tests: Vec<PathBuf> = test_sources
+            .iter()
+            .map(|s| build.join("test").join(stem(s)))
+            .collect();
+        let images = match &config.firmware {
+            Some(fw) => executables
+                .iter()
+                .flat_map(|exe| fw.outputs.iter().map(|f| exe.with_extension(f.extension())))
+                .collect(),
+            None => Vec::new(),
+        };
+        let library = has_lib.then(|| build.join("lib").join(format!("lib{}.a", modfile.name)));
+
+        let info = env.compiler_info(&config.compiler);
+        Ok(ModuleMetadata {
+            name: modfile.name.clone(),
+            version: config.version.clone(),
+            path: root.clone(),
+            external,
+            std: config.std.clone(),
+            defines: config.premacro.clone(),
+            sources: SourcesMetadata {
+                lib: lib_sources,
+                bin: bin_sources,
+                test: test_sources,
+                startup: config
+                    .firmware
+                    .iter()
+                    .flat_map(|fw| fw.startup.iter().map(|s| root.join(s)))
+                    .collect(),
+            },
+            include_paths: modfile.include_paths.clone().unwrap_or_default(),
+            dependencies,
+            system_libs: config.dep.lib.clone(),
+            pkg_config: config.dep.pkg_config.clone(),
+            compiler: CompilerMetadata {
+                config: config.compiler.clone(),
+                kind: info.filter(|i| i.found()).map(|i| i.kind.as_str()),
+                detected_version: info.filter(|i| i.found()).map(|i| i.version.clone()),
+                archiver: config.compiler.archiver(),
+                linker_driver: config.compiler.linker().to_string(),
+            },
+            firmware: config.firmware.is_some(),
+            prebuilt: modfile.prebuilt,
+            artifacts: ArtifactsMetadata {
+                pkg_config: library
+                    .as_ref()
+                    .map(|_| build.join("pkgconfig").join(format!("{}.pc", modfile.name))),
+                cmake: library.as_ref().map(|_| {
+                    build
+                        .join("cmake")
+                        .join(format!("{}Config.cmake", modfile.name))
+                }),
+                library,
+                executables,
+                tests,
+                images,
+            },
+        })
+    }
+}
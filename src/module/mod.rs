@@ -1,2 +1,11 @@
+pub mod coverage;
+pub mod env;
+pub mod export;
+pub mod external;
+pub mod install;
+pub mod lint;
 pub mod mod_file;
-pub mod manager;
\ No newline at end of file
+pub mod manager;
+pub mod metadata;
+pub mod package;
+pub mod template;
\ No newline at end of file
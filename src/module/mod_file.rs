@@ -1,13 +1,14 @@
-use crate::utils::logo::print_logo;
-use cc::Build;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::module::env::{BuildEnv, Profile, TestMessage};
+use crate::utils::diagnostics::{self, MessageFormat};
+use crate::utils::pkg_config::{self, PkgFlags};
 use crate::utils::yaml::Config;
-use crate::{logd, loge, logi};
+use crate::{logd, logi, logt, logw};
 
 /// 表示一个模块（mod）的元数据和构建上下文
 #[derive(Debug, Clone)]
@@ -18,12 +19,26 @@ pub struct ModFile {
     pub name: String,
     /// 可执行源文件列表（位于 bin/ 目录下，如 main.c）
     pub bin_sources: Option<Vec<PathBuf>>,
-    /// 头文件搜索路径（include/ 目录 + 配置中指定的路径）
+    /// 头文件搜索路径（include/ 目录及其子目录 + 配置中指定的路径）
     pub include_paths: Option<Vec<PathBuf>>,
     /// 库源文件列表（位于 src/ 目录下）
     pub lib_sources: Option<Vec<PathBuf>>,
+    /// 测试源文件列表（位于 test/ 目录下，每个文件生成一个测试程序）
+    pub test_sources: Option<Vec<PathBuf>>,
     /// 从 config.yaml 加载的配置
     pub config: Option<Config>,
+    /// configure 阶段由 pkg-config 解析出的编译/链接参数
+    pub pkg_flags: PkgFlags,
+    /// 依赖模块（含间接依赖）的 pkg-config 链接参数，按链接顺序排列，由 ModMgr 在 configure 阶段填入
+    pub dep_pkg_libs: Vec<String>,
+    /// 依赖模块（含间接依赖）的名字与模块目录，按链接顺序排列（被依赖者在后），由 ModMgr 在 configure 阶段填入
+    pub dep_lib_roots: Vec<(String, PathBuf)>,
+    /// 外部依赖模块名与模块目录（按声明顺序），由 ModMgr 解析外部依赖后填入
+    pub external_roots: Vec<(String, PathBuf)>,
+    /// 来自归档且附带当前目标预编译产物的模块，构建时跳过编译
+    pub prebuilt: bool,
+    /// 构建输出目录：build/，启用 sanitizer 时为其下的单独目录
+    pub build_dir: PathBuf,
 }
 
 impl ModFile {
@@ -35,28 +50,46 @@ impl ModFile {
             bin_sources: None,
             include_paths: None,
             lib_sources: None,
+            test_sources: None,
             config: None,
+            pkg_flags: PkgFlags::default(),
+            dep_pkg_libs: Vec::new(),
+            dep_lib_roots: Vec::new(),
+            external_roots: Vec::new(),
+            prebuilt: false,
+            build_dir: PathBuf::new(),
         }
     }
 
-    /// 从给定路径加载模块信息（目录结构 + 配置）
-    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+    /// 从给定路径加载模块信息（目录结构 + 配置），并按构建环境求值条件块
+    pub fn load(&mut self, path: &Path, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
         logi!("Loading module info from: {:?}", path);
         self.absolute_path = fs::canonicalize(path)?;
+        self.build_dir = env.build_dir(&self.absolute_path);
 
         // 加载各子目录中的文件
         self.bin_sources = Self::load_c_files(&path.join("bin")).ok();
         self.lib_sources = Self::load_all_files(&path.join("src")).ok();
-        self.include_paths = Self::load_all_files(&path.join("include")).ok();
+        self.test_sources = Self::load_c_files(&path.join("test")).ok();
+        self.include_paths = Self::load_include_dirs(&path.join("include")).ok();
 
-        // 加载 YAML 配置
-        self.config = Config::from_yaml(&path.join("config.yaml")).ok();
-
-        if let Some(cfg) = &self.config {
-            self.name = cfg.name.clone();
-        } else {
+        // 加载 YAML 配置，存在但无法解析时给出具体错误
+        let config_path = path.join("config.yaml");
+        if !config_path.is_file() {
             return Err("Failed to load config.yaml".into());
         }
+        let mut cfg = Config::from_yaml(&config_path)
+            .map_err(|e| format!("Invalid {}: {}", config_path.display(), e))?;
+        // 工具链先于条件块应用，条件块按工具链的目标求值
+        if let Some(toolchain) = &env.toolchain {
+            toolchain.apply(&mut cfg.compiler);
+        }
+        cfg.apply_conditionals(env);
+        self.name = cfg.name.clone();
+        self.config = Some(cfg);
+
+        // 过滤被排除的源文件
+        self.apply_excludes();
 
         // 从配置中补充 include 路径（依赖模块 + 显式 include）
         self.merge_includes()?;
@@ -70,48 +103,140 @@ impl ModFile {
             .ok_or_else(|| "Module configuration is missing".into())
     }
 
+    /// 配置阶段：生成版本头文件（dry-run 时不生成），并通过 pkg-config 解析 dep.pkg_config 中的系统库
+    pub fn configure(&mut self, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        if !self.prebuilt && !env.dry_run {
+            self.write_version_header()?;
+        }
+        let config = self.config()?;
+        if config.dep.pkg_config.is_empty() {
+            return Ok(());
+        }
+        self.pkg_flags =
+            pkg_config::resolve(&config.dep.pkg_config, config.compiler.sysroot.as_deref())
+                .map_err(|e| format!("Module '{}': {}", self.name, e))?;
+        logd!("pkg-config flags for {}: {:?}", self.name, self.pkg_flags);
+        Ok(())
+    }
+
     /// 执行完整构建流程：清理 → 编译库 → 编译二进制 → 链接
-    pub fn build(&mut self) -> Result<(), Box<dyn Error>> {
-        print_logo();
-        println!("Building module: {}", self.absolute_path.display());
+    pub fn build(&mut self, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        if self.prebuilt {
+            return Ok(());
+        }
 
-        self.init_build_dirs()?;
-        let local_lib = self.build_lib()?; // 构建静态库（.a）
-        let object_files = self.build_bin()?; // 编译 bin/ 下的 .c 为 .o
-        self.link_executables(&object_files, local_lib)?; // 链接生成可执行文件
+        self.init_build_dirs(env)?;
+        let local_lib = self.build_lib(env)?; // 构建静态库（.a）
+        let object_files = self.build_bin(env)?; // 编译 bin/ 下的 .c 为 .o
+        let startup_files = self.build_startup(env)?; // 编译固件启动文件
+        let bin_out_dir = self.build_bin_path(env)?;
+        self.link_executables(
+            env,
+            &object_files,
+            &startup_files,
+            local_lib.as_deref(),
+            &bin_out_dir,
+            true,
+        )?; // 链接生成可执行文件
 
         Ok(())
     }
 
     /// 运行所有已构建的可执行文件
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+    pub fn run(&self, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        if self.prebuilt {
+            return Ok(());
+        }
         let exe_paths = self.executables()?;
         for exe in exe_paths {
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = fs::metadata(&exe)?.permissions();
-                perms.set_mode(0o755);
-                fs::set_permissions(&exe, perms)?;
-            }
-
-            logi!("Running executable: {:?}", exe);
-            let status = Command::new(&exe).status()?;
-            if !status.success() {
+            let mut cmd = self.exec_cmd(env, &exe)?;
+            if !env.run_program("Running executable", &mut cmd)? {
                 return Err(format!("Execution failed: {}", exe.display()).into());
             }
         }
         Ok(())
     }
 
-    /// 判断路径是否为有效模块目录（能成功加载 info）
+    /// 编译并运行 test/ 下的测试程序，返回每个测试的名字与是否通过
+    pub fn test(&mut self, env: &BuildEnv) -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+        let sources = match &self.test_sources {
+            Some(list) if !list.is_empty() => list.clone(),
+            _ => return Ok(Vec::new()),
+        };
+
+        let object_dir = self.build_obj_path(env)?.join("test");
+        let test_out_dir = self.build_dir.join("test");
+        env.create_dir_all(&object_dir)?;
+        env.create_dir_all(&test_out_dir)?;
+
+        let mut object_files = Vec::new();
+        for source in &sources {
+            let obj = Self::object_path(&object_dir, source)?;
+            let mut cmd = self.compile_cmd(env, source, &obj)?;
+
+            if !env.exec("Compiling", &self.display(source), &mut cmd)? {
+                return Err(format!("Compilation failed: {}", source.display()).into());
+            }
+            object_files.push(obj);
+        }
+
+        let local_lib = self.library().or_else(|| {
+            (env.dry_run && Self::has_lib_sources(&self.absolute_path)).then(|| self.lib_path())
+        });
+        self.link_executables(
+            env,
+            &object_files,
+            &[],
+            local_lib.as_deref(),
+            &test_out_dir,
+            false,
+        )?;
+
+        let mut results = Vec::new();
+        // dry-run 只输出命令，不运行测试程序
+        if env.dry_run {
+            return Ok(results);
+        }
+        for obj in &object_files {
+            let name = Self::exe_name(obj)?;
+            let exe = test_out_dir.join(&name);
+            let mut cmd = self.exec_cmd(env, &exe)?;
+
+            let passed = env.run_program("Running test", &mut cmd)?;
+            let line = format!(
+                "test {}::{} ... {}",
+                self.name,
+                name,
+                if passed { "ok" } else { "FAILED" }
+            );
+            match env.message_format {
+                MessageFormat::Human => println!("{}", line),
+                MessageFormat::Json => {
+                    diagnostics::emit_json(&TestMessage {
+                        reason: "test",
+                        module: &self.name,
+                        name: &name,
+                        passed,
+                    })?;
+                    env.progress.println(&line);
+                }
+            }
+            results.push((name, passed));
+        }
+        Ok(results)
+    }
+
+    /// 判断路径是否为模块目录（含 config.yaml），配置错误在加载时报告
     pub fn is_mod_dir(path: &Path) -> bool {
-        let mut mod_file = Self::new();
-        mod_file.load(path).is_ok()
+        path.join("config.yaml").is_file()
     }
 
     /// 清理 build/ 目录
     pub fn clean_build(&mut self) -> Result<(), Box<dyn Error>> {
+        // 预编译产物来自归档，清理后无法重新生成
+        if self.prebuilt {
+            return Ok(());
+        }
         let build_dir = self.absolute_path.join("build");
         if build_dir.exists() {
             fs::remove_dir_all(build_dir)?;
@@ -119,25 +244,71 @@ impl ModFile {
         Ok(())
     }
 
-    /// 生成新模块骨架目录（bin/, include/, src/, config.yaml）
-    pub fn gen(mod_name: &str) -> Result<(), Box<dyn Error>> {
-        let base = PathBuf::from(mod_name);
-        for dir in ["bin", "include", "src"] {
-            fs::create_dir_all(base.join(dir))?;
+    // ———————————————————————— 私有辅助方法 ————————————————————————
+    /// 生成执行产物的命令：配置了 runner 时经由 runner（如 qemu）执行
+    fn exec_cmd(&self, env: &BuildEnv, exe: &Path) -> Result<Command, Box<dyn Error>> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(exe)?.permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(exe, perms)?;
+        }
+
+        let config = self.config()?;
+        let mut cmd = match config.compiler.runner_args()? {
+            Some(runner) => {
+                let mut cmd = Command::new(&runner[0]);
+                cmd.args(&runner[1..]).arg(exe);
+                cmd
+            }
+            None => {
+                self.check_host_arch(env, exe)?;
+                Command::new(exe)
+            }
+        };
+        for (key, value) in env.sanitizer_options() {
+            if std::env::var_os(key).is_none() {
+                cmd.env(key, value);
+            }
         }
+        Ok(cmd)
+    }
+
+    /// 未配置 runner 时检查产物能否在主机上直接执行
+    fn check_host_arch(&self, env: &BuildEnv, exe: &Path) -> Result<(), Box<dyn Error>> {
+        let config = self.config()?;
 
-        let config_path = base.join("config.yaml");
-        let default_config = Config::new();
-        if let Ok(yaml) = default_config.to_yaml() {
-            fs::write(config_path, yaml)?;
+        // 目标架构与主机不同且未配置 runner 时，直接执行必然失败
+        let target = config.target(env);
+        let arch = |triple: &str| triple.split('-').next().unwrap_or("").to_string();
+        if !Self::host_runs_arch(&arch(&env.host), &arch(target)) {
+            return Err(format!(
+                "Cannot run {} built for {} on {}, set compiler.runner (e.g. qemu-aarch64 -L $SYSROOT)",
+                exe.display(),
+                target,
+                env.host
+            )
+            .into());
         }
         Ok(())
     }
 
-    // ———————————————————————— 私有辅助方法 ————————————————————————
-    /// 获取所有可执行文件路径（build/bin/ 下）
-    fn executables(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        let bin_dir = self.build_bin_path()?;
+    /// 主机架构能否直接执行目标架构的程序：同一架构（含别名），或 x86_64 主机运行 32 位 x86 程序
+    fn host_runs_arch(host: &str, target: &str) -> bool {
+        let normalize = |arch: &str| match arch {
+            "i386" | "i486" | "i586" | "i686" | "x86" => "x86".to_string(),
+            "amd64" => "x86_64".to_string(),
+            "arm64" => "aarch64".to_string(),
+            other => other.to_string(),
+        };
+        let (host, target) = (normalize(host), normalize(target));
+        host == target || (host == "x86_64" && target == "x86")
+    }
+
+    /// 获取所有可执行文件路径（构建目录的 bin/ 下）
+    pub fn executables(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let bin_dir = self.build_dir.join("bin");
         let mut paths = Vec::new();
 
         if let Some(bin_list) = &self.bin_sources {
@@ -155,110 +326,146 @@ impl ModFile {
         Ok(paths)
     }
 
-    /// 构建静态库（使用 cc crate）
-    fn build_lib(&self) -> Result<Option<PathBuf>, Box<dyn Error>> {
-        if self.lib_sources.is_none() {
-            return Ok(None);
+    /// 构建该模块需要的编译、归档与链接步骤数，tests 为真时包含测试程序
+    pub fn step_count(&self, tests: bool) -> usize {
+        if self.prebuilt {
+            return 0;
+        }
+        let count = |list: &Option<Vec<PathBuf>>| list.as_ref().map_or(0, |l| l.len());
+        let lib = self
+            .lib_sources
+            .iter()
+            .flatten()
+            .filter(|p| Self::is_source(p))
+            .count();
+        let bins = count(&self.bin_sources);
+        let startup = self
+            .config
+            .as_ref()
+            .and_then(|c| c.firmware.as_ref())
+            .map_or(0, |fw| fw.startup.len());
+        let mut steps = lib + usize::from(lib > 0) + bins * 2 + startup;
+        if tests {
+            steps += count(&self.test_sources) * 2;
         }
+        steps
+    }
 
-        let config = self.config()?;
-        let lib_name = self.name.as_str();
-        let target = config
-            .compiler
-            .target
-            .as_deref()
-            .unwrap_or("x86_64-unknown-linux-gnu");
+    /// 进度行中显示的路径：<模块名>/<相对模块根目录的路径>
+    pub fn display(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.absolute_path) {
+            Ok(rel) => format!("{}/{}", self.name, rel.display()),
+            Err(_) => path.display().to_string(),
+        }
+    }
 
-        let object_dir = self.build_obj_path()?;
-        let lib_dir = self.build_lib_path()?;
+    /// 已构建的静态库路径（build/lib/lib<name>.a），未构建时返回 None
+    pub fn library(&self) -> Option<PathBuf> {
+        let lib = self.lib_path();
+        lib.exists().then_some(lib)
+    }
 
-        // 使用 cc 构建静态库
-        let mut builder = Build::new();
-        if let Some(sources) = &self.lib_sources {
-            builder.files(sources);
-        }
-        if let Some(includes) = &self.include_paths {
-            builder.includes(includes);
-        }
+    fn lib_path(&self) -> PathBuf {
+        self.build_dir
+            .join("lib")
+            .join(format!("lib{}.a", self.name))
+    }
 
-        // 从配置应用编译参数
-        builder
-            .target(target)
-            .host(target)
-            .std(&config.std)
-            .out_dir(&object_dir)
-            .opt_level(2);
+    /// 模块 src/ 下是否有可编译的源文件，即构建后是否会生成静态库
+    fn has_lib_sources(root: &Path) -> bool {
+        fs::read_dir(root.join("src"))
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .any(|e| Self::is_source(&e.path()))
+            })
+            .unwrap_or(false)
+    }
 
-        // 应用全局宏定义
-        for macro_def in &config.premacro {
-            builder.define(macro_def.as_str(), None);
+    /// 构建静态库：逐个编译 src/ 下的源文件，再用归档工具打包为 lib<name>.a
+    fn build_lib(&self, env: &BuildEnv) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let sources: Vec<&PathBuf> = match &self.lib_sources {
+            Some(list) => list.iter().filter(|p| Self::is_source(p)).collect(),
+            None => Vec::new(),
+        };
+        if sources.is_empty() {
+            return Ok(None);
         }
 
-        builder.compile(lib_name);
+        let config = self.config()?;
+        let object_dir = self.build_obj_path(env)?.join("src");
+        let lib_dir = self.build_lib_path(env)?;
+        env.create_dir_all(&object_dir)?;
 
-        // 移动库文件到 build/lib/
-        let lib_file = if cfg!(windows) {
-            object_dir.join(format!("{}.lib", lib_name))
-        } else {
-            object_dir.join(format!("lib{}.a", lib_name))
-        };
+        let mut objects = Vec::new();
+        for source in sources {
+            let obj = Self::object_path(&object_dir, source)?;
+            let mut cmd = self.compile_cmd(env, source, &obj)?;
+
+            if !env.exec("Compiling", &self.display(source), &mut cmd)? {
+                return Err(format!("Compilation failed: {}", source.display()).into());
+            }
+            objects.push(obj);
+        }
+
+        // 归档为静态库，先删除旧库避免残留过期目标文件
+        let dest = lib_dir.join(format!("lib{}.a", self.name));
+        if dest.exists() && !env.dry_run {
+            fs::remove_file(&dest)?;
+        }
+        let mut cmd = Command::new(config.compiler.archiver());
+        cmd.arg("rcs").arg(&dest).args(&objects);
 
-        let dest = lib_dir.join(lib_file.file_name().unwrap());
-        fs::rename(&lib_file, &dest)?;
+        if !env.exec("Archiving", &self.display(&dest), &mut cmd)? {
+            return Err(format!("Archiving failed: {}", dest.display()).into());
+        }
         Ok(Some(dest))
     }
 
     /// 编译 bin/ 下的 .c 文件为 .o 对象文件
-    fn build_bin(&mut self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        let object_dir = self.build_obj_path()?;
-        let config = self.config()?;
-
+    fn build_bin(&mut self, env: &BuildEnv) -> Result<Vec<PathBuf>, Box<dyn Error>> {
         // 若无二进制源文件，直接返回空
         let sources = match &self.bin_sources {
             Some(list) if !list.is_empty() => list,
             _ => return Ok(Vec::new()),
         };
 
-        let cc = &config.compiler.cc;
+        let object_dir = self.build_obj_path(env)?.join("bin");
+        env.create_dir_all(&object_dir)?;
+
         let mut object_files = Vec::new();
         for source in sources {
-            let stem = source
-                .file_stem()
-                .ok_or_else(|| format!("Invalid source filename: {}", source.display()))?;
-            let obj = object_dir.join(stem).with_extension("o");
-
-            let mut cmd = Command::new(cc);
+            let obj = Self::object_path(&object_dir, source)?;
+            let mut cmd = self.compile_cmd(env, source, &obj)?;
 
-            // 编译标准
-            cmd.arg(format!("-std={}", config.std));
-
-            // 全局宏定义
-            for macro_def in &config.premacro {
-                cmd.arg(format!("-D{}", macro_def));
+            if !env.exec("Compiling", &self.display(source), &mut cmd)? {
+                return Err(format!("Compilation failed: {}", source.display()).into());
             }
+            object_files.push(obj);
+        }
+        Ok(object_files)
+    }
 
-            // include 路径
-            if let Some(includes) = &self.include_paths {
-                for inc in includes {
-                    cmd.arg("-I").arg(inc);
-                }
-            }
+    /// 编译固件配置中的启动文件，未配置固件时返回空
+    fn build_startup(&self, env: &BuildEnv) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let startup = match &self.config()?.firmware {
+            Some(fw) if !fw.startup.is_empty() => &fw.startup,
+            _ => return Ok(Vec::new()),
+        };
 
-            // sysroot（交叉编译）
-            if let Some(sysroot) = &config.compiler.sysroot {
-                cmd.arg("--sysroot").arg(sysroot);
-            }
+        let object_dir = self.build_obj_path(env)?.join("startup");
+        env.create_dir_all(&object_dir)?;
 
-            // 额外 flags
-            for flag in &config.compiler.flags {
-                cmd.arg(flag);
+        let mut object_files = Vec::new();
+        for rel in startup {
+            let source = self.absolute_path.join(rel);
+            if !source.is_file() {
+                return Err(format!("Startup file not found: {}", source.display()).into());
             }
+            let obj = Self::object_path(&object_dir, &source)?;
+            let mut cmd = self.compile_cmd(env, &source, &obj)?;
 
-            cmd.arg("-c").arg(source).arg("-o").arg(&obj);
-
-            logi!("Compiling: {:?}", cmd);
-            let status = cmd.status()?;
-            if !status.success() {
+            if !env.exec("Compiling", &self.display(&source), &mut cmd)? {
                 return Err(format!("Compilation failed: {}", source.display()).into());
             }
             object_files.push(obj);
@@ -266,16 +473,192 @@ impl ModFile {
         Ok(object_files)
     }
 
-    /// 获取依赖模块的库路径和链接目录
-    fn dep_libs(&self) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Box<dyn Error>> {
+    /// 生成单个源文件的编译命令，库与二进制共用，保证参数一致
+    fn compile_cmd(
+        &self,
+        env: &BuildEnv,
+        source: &Path,
+        obj: &Path,
+    ) -> Result<Command, Box<dyn Error>> {
+        let config = self.config()?;
+        let mut cmd = Command::new(&config.compiler.cc);
+        cmd.args(self.compile_args(env)?);
+
+        // 构建配置档对应的优化等级，覆盖率构建在额外 flags 之后强制 -O0 -g
+        if !env.coverage {
+            match env.profile {
+                Profile::Debug => cmd.args(["-O0", "-g"]),
+                Profile::Release => cmd.arg("-O2"),
+            };
+        }
+
+        if config.firmware.as_ref().is_some_and(|fw| fw.freestanding) {
+            cmd.arg("-ffreestanding");
+        }
+
+        // 警告策略，放在额外 flags 之前以便后者覆盖
+        let clang = env.is_clang(&config.compiler);
+        cmd.args(config.warnings.flags(clang, env.deny_warnings));
+
+        if let Some(flag) = env.sanitize_flag() {
+            cmd.arg(flag).arg("-fno-omit-frame-pointer");
+        }
+
+        // 额外 flags
+        for flag in &config.compiler.flags {
+            cmd.arg(flag);
+        }
+        if env.coverage {
+            cmd.args(["--coverage", "-O0", "-g"]);
+        }
+
+        // --message-format json 时让支持的编译器直接输出 JSON 诊断
+        let json_supported = env
+            .compiler_info(&config.compiler)
+            .is_some_and(|info| info.flags.get(diagnostics::JSON_FLAG) == Some(&true));
+        if env.message_format == MessageFormat::Json && json_supported {
+            cmd.arg(diagnostics::JSON_FLAG);
+        }
+
+        cmd.arg("-c").arg(source).arg("-o").arg(obj);
+        Ok(cmd)
+    }
+
+    /// 编译与静态分析共用的参数：编译标准、全局宏定义、include 路径、
+    /// pkg-config 给出的编译参数与目标相关参数
+    pub(crate) fn compile_args(&self, env: &BuildEnv) -> Result<Vec<String>, Box<dyn Error>> {
+        let config = self.config()?;
+        let mut args = vec![format!("-std={}", config.std)];
+        args.extend(config.premacro.iter().map(|m| format!("-D{}", m)));
+        for inc in self.include_paths.iter().flatten() {
+            args.push("-I".to_string());
+            args.push(inc.to_string_lossy().into_owned());
+        }
+        args.extend(self.pkg_flags.cflags.iter().cloned());
+        args.extend(config.compiler.target_args(env.is_clang(&config.compiler)));
+        Ok(args)
+    }
+
+    /// 目标相关参数：clang 需显式 --target，gcc 由交叉编译器名决定目标；sysroot 两者通用
+    fn target_args(&self, env: &BuildEnv, cmd: &mut Command) -> Result<(), Box<dyn Error>> {
+        let compiler = &self.config()?.compiler;
+        cmd.args(compiler.target_args(env.is_clang(compiler)));
+        Ok(())
+    }
+
+    /// 关联已解析的外部依赖模块，并加入其 include/ 与生成头文件目录
+    pub fn add_external(&mut self, name: &str, root: &Path) {
+        let includes = self.include_paths.get_or_insert_with(Vec::new);
+        if let Ok(abs) = fs::canonicalize(root.join("include")) {
+            if !includes.contains(&abs) {
+                includes.push(abs);
+            }
+        }
+        let generated = Self::generated_include_dir(root);
+        if !includes.contains(&generated) {
+            includes.push(generated);
+        }
+        if !self.external_roots.iter().any(|(n, _)| n == name) {
+            self.external_roots
+                .push((name.to_string(), root.to_path_buf()));
+        }
+    }
+
+    /// 生成头文件（如 <name>_version.h）所在目录：build/include
+    fn generated_include_dir(root: &Path) -> PathBuf {
+        root.join("build").join("include")
+    }
+
+    /// 生成 build/include/<name>_version.h，提供 <NAME>_VERSION_MAJOR/MINOR/PATCH/STRING/NUMBER 宏，
+    /// 内容未变化时不重写
+    pub fn write_version_header(&self) -> Result<PathBuf, Box<dyn Error>> {
         let config = self.config()?;
+        let version = semver::Version::parse(&config.version).map_err(|e| {
+            format!(
+                "Module '{}' has invalid version '{}': {}",
+                self.name, config.version, e
+            )
+        })?;
+        let prefix: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let text = format!(
+            "/* Generated by hk, do not edit */\n\
+             #ifndef {p}_VERSION_H\n\
+             #define {p}_VERSION_H\n\
+             \n\
+             #define {p}_VERSION_MAJOR {major}\n\
+             #define {p}_VERSION_MINOR {minor}\n\
+             #define {p}_VERSION_PATCH {patch}\n\
+             #define {p}_VERSION_STRING \"{version}\"\n\
+             /* MAJOR * 10000 + MINOR * 100 + PATCH */\n\
+             #define {p}_VERSION_NUMBER {number}\n\
+             \n\
+             #endif /* {p}_VERSION_H */\n",
+            p = prefix,
+            major = version.major,
+            minor = version.minor,
+            patch = version.patch,
+            version = version,
+            number = version.major * 10000 + version.minor * 100 + version.patch,
+        );
+
+        let dir = Self::generated_include_dir(&self.absolute_path);
+        let path = dir.join(format!("{}_version.h", self.name));
+        if fs::read_to_string(&path).ok().as_deref() != Some(text.as_str()) {
+            fs::create_dir_all(&dir)?;
+            fs::write(&path, text)?;
+            logd!("Generated {:?}", path);
+        }
+        Ok(path)
+    }
+
+    /// 直接依赖模块的名称与根目录：mod_deps 中的项目内模块 + 外部模块
+    pub fn dep_roots(&self) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+        let config = self.config()?;
+        let mut roots: Vec<(String, PathBuf)> = config
+            .dep
+            .mod_deps
+            .iter()
+            .map(|d| (d.name(), self.absolute_path.join(&d.path)))
+            .collect();
+        roots.extend(self.external_roots.iter().cloned());
+        Ok(roots)
+    }
+
+    /// 获取依赖模块（含间接依赖）的库路径和链接目录，静态库不携带其依赖
+    fn dep_libs(&self, env: &BuildEnv) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Box<dyn Error>> {
         let mut lib_dirs = Vec::new();
         let mut lib_files = Vec::new();
 
-        for dep_name in &config.dep.mod_deps {
-            let dep_root = self.absolute_path.join(dep_name);
-            let lib_dir = dep_root.join("build").join("lib");
+        for (dep_name, dep_root) in &self.dep_lib_roots {
+            let lib_dir = env.build_dir(dep_root).join("lib");
             let lib_file = lib_dir.join(format!("lib{}.a", dep_name));
+            if !Self::has_lib_sources(dep_root) {
+                // 只有头文件的依赖模块不生成库
+                logd!(
+                    "Dependency {} has no library sources, not linking it",
+                    dep_name
+                );
+                continue;
+            }
+            // dry-run 时依赖尚未构建，库文件不存在属正常
+            if !lib_file.exists() && !env.dry_run {
+                return Err(format!(
+                    "Library of dependency {} not found: {}",
+                    dep_name,
+                    lib_file.display()
+                )
+                .into());
+            }
 
             lib_dirs.push(lib_dir);
             lib_files.push(lib_file);
@@ -283,33 +666,36 @@ impl ModFile {
         Ok((lib_dirs, lib_files))
     }
 
-    /// 链接所有可执行文件
+    /// 链接所有可执行文件到 out_dir；firmware 为真时固件模块额外处理链接脚本、映射文件与镜像生成，
+    /// 测试程序在主机或 runner 上运行，不做这些处理
     fn link_executables(
-        &mut self,
+        &self,
+        env: &BuildEnv,
         object_files: &[PathBuf],
-        local_lib: Option<PathBuf>,
+        startup_files: &[PathBuf],
+        local_lib: Option<&Path>,
+        out_dir: &Path,
+        firmware: bool,
     ) -> Result<(), Box<dyn Error>> {
-        let bin_out_dir = self.build_bin_path()?;
-        let local_lib_dir = self.build_lib_path()?;
+        let local_lib_dir = self.build_lib_path(env)?;
 
-        let (dep_lib_dirs, dep_lib_files) = self.dep_libs()?;
+        let (dep_lib_dirs, dep_lib_files) = self.dep_libs(env)?;
         let config = self.config()?;
-        let cc = &config.compiler.cc;
+        let linker = config.compiler.linker();
+        let firmware = config.firmware.as_ref().filter(|_| firmware);
 
         for obj in object_files {
-            let exe = bin_out_dir.join(
-                obj.file_stem()
-                    .ok_or_else(|| format!("Invalid object file: {}", obj.display()))?,
-            );
+            let exe = out_dir.join(Self::exe_name(obj)?);
 
-            let mut cmd = Command::new(cc);
+            let mut cmd = Command::new(linker);
             cmd.arg("-L").arg(&local_lib_dir);
             for dir in &dep_lib_dirs {
                 cmd.arg("-L").arg(dir);
             }
+            cmd.args(startup_files);
             cmd.arg(obj);
 
-            if let Some(lib) = &local_lib {
+            if let Some(lib) = local_lib {
                 cmd.arg(lib);
             }
             for lib in &dep_lib_files {
@@ -318,25 +704,83 @@ impl ModFile {
 
             cmd.arg("-o").arg(&exe);
 
-            // sysroot（交叉编译）
-            if let Some(sysroot) = &config.compiler.sysroot {
-                cmd.arg("--sysroot").arg(sysroot);
+            self.target_args(env, &mut cmd)?;
+
+            if let Some(flag) = env.sanitize_flag() {
+                cmd.arg(flag);
+            }
+            if env.coverage {
+                cmd.arg("--coverage");
             }
 
             // 额外 flags
-            for flag in &config.compiler.flags {
+            for flag in config.compiler.flags.iter().chain(&config.compiler.ldflags) {
                 cmd.arg(flag);
             }
 
             for sys_lib in self.sys_libs() {
                 cmd.arg(sys_lib);
             }
+            cmd.args(&self.pkg_flags.libs);
+            cmd.args(&self.dep_pkg_libs);
+
+            if let Some(fw) = firmware {
+                if let Some(script) = &fw.linker_script {
+                    let script = self.absolute_path.join(script);
+                    if !script.is_file() {
+                        return Err(format!("Linker script not found: {}", script.display()).into());
+                    }
+                    cmd.arg("-T").arg(script);
+                }
+                if fw.nostdlib {
+                    cmd.arg("-nostdlib");
+                }
+                if fw.map {
+                    let map = exe.with_extension("map");
+                    cmd.arg(format!("-Wl,-Map={}", map.display()));
+                }
+            }
 
-            logi!("Linking: {:?}", cmd);
-            let status = cmd.status()?;
-            if !status.success() {
+            if !env.exec("Linking", &self.display(&exe), &mut cmd)? {
                 return Err(format!("Linking failed: {}", exe.display()).into());
             }
+
+            if firmware.is_some() {
+                self.post_link(env, &exe)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 固件链接后处理：objcopy 生成 .bin/.hex 镜像，并输出 size 报告
+    fn post_link(&self, env: &BuildEnv, exe: &Path) -> Result<(), Box<dyn Error>> {
+        let config = self.config()?;
+        let fw = match &config.firmware {
+            Some(fw) => fw,
+            None => return Ok(()),
+        };
+
+        for format in &fw.outputs {
+            let image = exe.with_extension(format.extension());
+            let mut cmd = Command::new(config.compiler.objcopy());
+            cmd.arg("-O").arg(format.bfd_name()).arg(exe).arg(&image);
+
+            if !env.exec("Objcopy", &self.display(&image), &mut cmd)? {
+                return Err(format!("Objcopy failed: {}", image.display()).into());
+            }
+            if !env.dry_run {
+                env.progress
+                    .println(&format!("Generated image: {}", image.display()));
+            }
+        }
+
+        if fw.size {
+            let mut cmd = Command::new(config.compiler.size_tool());
+            cmd.arg(exe);
+
+            if !env.exec("Size", &self.display(exe), &mut cmd)? {
+                return Err(format!("Size report failed: {}", exe.display()).into());
+            }
         }
         Ok(())
     }
@@ -351,42 +795,43 @@ impl ModFile {
     }
 
     /// 确保 build/ 子目录存在
-    fn init_build_dirs(&self) -> Result<(), Box<dyn Error>> {
-        let base = self.absolute_path.join("build");
+    fn init_build_dirs(&self, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        let base = &self.build_dir;
         for subdir in ["bin", "object", "lib"] {
-            fs::create_dir_all(base.join(subdir))?;
+            env.create_dir_all(&base.join(subdir))?;
         }
         logd!("Build directories created under: {:?}", base);
         Ok(())
     }
 
-    /// 获取构建输出路径（带存在性检查）
-    fn build_bin_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let path = self.absolute_path.join("build").join("bin");
-        if !path.exists() {
+    /// 获取构建输出路径（带存在性检查，dry-run 时目录不会被创建，不检查）
+    fn build_bin_path(&self, env: &BuildEnv) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.build_dir.join("bin");
+        if !path.exists() && !env.dry_run {
             return Err("build bin path not exists".into());
         }
         Ok(path)
     }
 
-    fn build_obj_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let path = self.absolute_path.join("build").join("object");
-        if !path.exists() {
+    fn build_obj_path(&self, env: &BuildEnv) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.build_dir.join("object");
+        if !path.exists() && !env.dry_run {
             return Err("build object path not exists".into());
         }
         Ok(path)
     }
 
-    fn build_lib_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let path = self.absolute_path.join("build").join("lib");
-        if !path.exists() {
+    fn build_lib_path(&self, env: &BuildEnv) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.build_dir.join("lib");
+        if !path.exists() && !env.dry_run {
             return Err("build lib path not exists".into());
         }
         Ok(path)
     }
 
     /// 从 config.yaml 合并 include 路径：
-    /// - 依赖模块的 include/
+    /// - 本模块的生成头文件目录 build/include
+    /// - 依赖模块的 include/ 与 build/include
     /// - 配置中显式列出的 include 路径
     fn merge_includes(&mut self) -> Result<(), Box<dyn Error>> {
         let config = match &self.config {
@@ -394,17 +839,30 @@ impl ModFile {
             None => return Err(format!("No config found in {:?}", self.absolute_path).into()), // 无配置则跳过
         };
 
+        let dep_roots: Vec<PathBuf> = config
+            .dep
+            .mod_deps
+            .iter()
+            .map(|d| self.absolute_path.join(&d.path))
+            .collect();
         let includes = self.include_paths.get_or_insert_with(Vec::new);
+        includes.push(Self::generated_include_dir(&self.absolute_path));
 
-        // 添加依赖模块的 include/
-        for dep in &config.dep.mod_deps {
-            let path = self.absolute_path.join(dep).join("include");
+        // 添加依赖模块的 include/ 与 build/include
+        for root in dep_roots {
+            let path = root.join("include");
             if let Ok(abs) = fs::canonicalize(&path) {
                 if !includes.contains(&abs) {
                     includes.push(abs);
                 }
             } else {
-                loge!("Failed to canonicalize dependency include: {:?}", path);
+                logw!("Failed to canonicalize dependency include: {:?}", path);
+            }
+            if let Ok(root) = fs::canonicalize(&root) {
+                let generated = Self::generated_include_dir(&root);
+                if !includes.contains(&generated) {
+                    includes.push(generated);
+                }
             }
         }
 
@@ -416,7 +874,7 @@ impl ModFile {
                     includes.push(abs);
                 }
             } else {
-                loge!("Failed to canonicalize include path: {:?}", path);
+                logw!("Failed to canonicalize include path: {:?}", path);
             }
         }
 
@@ -425,7 +883,7 @@ impl ModFile {
 
     /// 加载目录下所有 .c 文件（用于 bin/）
     fn load_c_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        logi!("Scanning C files in: {:?}", dir);
+        logt!("Scanning C files in: {:?}", dir);
         let mut files = Vec::new();
 
         if !dir.exists() {
@@ -435,18 +893,16 @@ impl ModFile {
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
-            if path.is_file() {
-                if path.extension().map_or(false, |ext| ext == "c") {
-                    files.push(fs::canonicalize(&path)?);
-                }
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "c") {
+                files.push(fs::canonicalize(&path)?);
             }
         }
         Ok(files)
     }
 
-    /// 加载目录下所有文件（用于 src/ 和 include/）
+    /// 加载目录下所有文件（用于 src/）
     fn load_all_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        logi!("Scanning all files in: {:?}", dir);
+        logt!("Scanning all files in: {:?}", dir);
         let mut files = Vec::new();
 
         if !dir.exists() {
@@ -462,6 +918,73 @@ impl ModFile {
         }
         Ok(files)
     }
+
+    /// 是否为可编译的源文件（src/ 下可能混有头文件等）
+    fn is_source(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext == "c" || ext == "s" || ext == "S")
+    }
+
+    /// 源文件对应的目标文件路径：保留扩展名（foo.c -> foo.c.o），避免 foo.c 与 foo.S 冲突
+    pub(crate) fn object_path(object_dir: &Path, source: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let name = source
+            .file_name()
+            .ok_or_else(|| format!("Invalid source filename: {}", source.display()))?;
+        Ok(object_dir.join(format!("{}.o", name.to_string_lossy())))
+    }
+
+    /// 目标文件对应的可执行文件名：去掉 .o 与源文件扩展名（main.c.o -> main）
+    fn exe_name(obj: &Path) -> Result<String, Box<dyn Error>> {
+        obj.file_stem()
+            .and_then(|name| Path::new(name).file_stem())
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("Invalid object file: {}", obj.display()).into())
+    }
+
+    /// 加载头文件目录：include/ 本身及其下一级子目录
+    fn load_include_dirs(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        logt!("Scanning include dirs in: {:?}", dir);
+        let mut dirs = Vec::new();
+
+        if !dir.exists() {
+            return Ok(dirs); // 目录可选
+        }
+
+        dirs.push(fs::canonicalize(dir)?);
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(fs::canonicalize(&path)?);
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// 按配置中的 exclude 规则过滤 src/ 与 bin/ 下的源文件
+    fn apply_excludes(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        if config.exclude.is_empty() {
+            return;
+        }
+
+        let root = &self.absolute_path;
+        let keep = |path: &PathBuf| {
+            let rel = path.strip_prefix(root).unwrap_or(path);
+            let excluded = config.is_excluded(&rel.to_string_lossy());
+            if excluded {
+                logd!("Excluding source: {:?}", rel);
+            }
+            !excluded
+        };
+        for list in [&mut self.bin_sources, &mut self.lib_sources]
+            .into_iter()
+            .flatten()
+        {
+            list.retain(keep);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -479,8 +1002,8 @@ impl ProjectMap {
             index: HashMap::new(),
         }
     }
-    pub fn discover_mods(&mut self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
-        let current_dir = path.clone();
+    pub fn discover_mods(&mut self, path: &Path, env: &BuildEnv) -> Result<(), Box<dyn Error>> {
+        let current_dir = path.to_path_buf();
 
         //读取当前目录下的所有模块目录
         let mods: Vec<PathBuf> = fs::read_dir(&current_dir)?
@@ -503,7 +1026,7 @@ impl ProjectMap {
 
         for path in &mods {
             let mut modfile = ModFile::new();
-            modfile.load(path)?;
+            modfile.load(path, env)?;
             self.mod_names.push(modfile.name.clone());
             self.index.insert(modfile.name.clone(), path.clone());
             self.indices.insert(modfile.name.clone(), modfile);
@@ -511,3 +1034,50 @@ impl ProjectMap {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::probe::{self, CompilerInfo, CompilerKind};
+
+    #[test]
+    fn json_flag_follows_probe_with_target_args() {
+        let dir = std::env::temp_dir().join(format!("hk-mod-file-json-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("config.yaml"),
+            "name: cross\nversion: 0.1.0\nstd: c99\npremacro: []\n\
+             dep:\n  include: []\n  mod_deps: []\n  lib: []\n\
+             compiler:\n  cc: gcc\n  sysroot: /opt/sysroot\n",
+        )
+        .unwrap();
+
+        let mut env = BuildEnv {
+            message_format: MessageFormat::Json,
+            ..BuildEnv::default()
+        };
+        let mut modfile = ModFile::new();
+        modfile.load(&dir, &env).unwrap();
+        let target_args = vec!["--sysroot".to_string(), "/opt/sysroot".to_string()];
+        env.compilers.insert(
+            probe::probe_key("gcc", &target_args),
+            CompilerInfo {
+                cc: "gcc".into(),
+                path: Some(PathBuf::from("/usr/bin/gcc")),
+                kind: CompilerKind::Gcc,
+                version: "13.2.0".into(),
+                mtime: 0,
+                target_args,
+                stds: Default::default(),
+                flags: [(diagnostics::JSON_FLAG.to_string(), true)].into(),
+            },
+        );
+
+        let cmd = modfile
+            .compile_cmd(&env, &dir.join("src").join("a.c"), &dir.join("a.c.o"))
+            .unwrap();
+        assert!(cmd.get_args().any(|arg| arg == diagnostics::JSON_FLAG));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
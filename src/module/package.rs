@@ -0,0 +1,301 @@
+use crate::logi;
+use crate::module::{env::BuildEnv, mod_file::ModFile};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// 归档根目录中的清单文件名
+pub const MANIFEST: &str = "hk-package.yaml";
+
+/// 模块归档的清单：名称、版本、预编译目标与各文件的 sha256
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackageManifest {
+    pub name: String,
+    pub version: String,
+    /// 预编译产物的目标三元组，仅含源码时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// 预编译产物的构建配置档
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// 归档内相对路径 → sha256
+    pub files: BTreeMap<String, String>,
+}
+
+impl PackageManifest {
+    /// 读取解压目录中的清单
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = dir.join(MANIFEST);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(serde_yaml::from_str(&text)?)
+    }
+
+    /// 预编译产物是否可直接用于当前构建（目标与配置档一致）
+    pub fn prebuilt_for(&self, target: &str, env: &BuildEnv) -> bool {
+        // 预编译产物不含 sanitizer 与覆盖率插桩
+        self.target.as_deref() == Some(target)
+            && self.profile.as_deref() == Some(env.profile.as_str())
+            && env.sanitize.is_empty()
+            && !env.coverage
+    }
+}
+
+/// 计算数据的 sha256 十六进制串
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(sha256_hex(&fs::read(path)?))
+}
+
+/// 收集模块源码树中需要打包的文件（跳过 build/ 与隐藏目录），返回相对路径
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || (dir == root && name == "build") {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push(path.strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+impl ModFile {
+    /// 将模块打包为 <out_dir>/<name>-<version>[-<target>].tar.gz，并生成同名 .sha256 校验文件。
+    /// prebuilt 为真时额外打包 build/lib 与 build/bin 下的已构建产物
+    pub fn package(
+        &self,
+        env: &BuildEnv,
+        out_dir: &Path,
+        prebuilt: bool,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let config = self.config()?;
+        let base = format!("{}-{}", self.name, config.version);
+
+        let mut files = Vec::new();
+        collect_files(&self.absolute_path, &self.absolute_path, &mut files)?;
+
+        let mut manifest = PackageManifest {
+            name: self.name.clone(),
+            version: config.version.clone(),
+            target: None,
+            profile: None,
+            files: BTreeMap::new(),
+        };
+        let mut archive_name = base.clone();
+        if prebuilt {
+            let target = config.target(env).to_string();
+            archive_name = format!("{}-{}", base, target);
+            if let Some(lib) = self.library() {
+                files.push(lib.strip_prefix(&self.absolute_path)?.to_path_buf());
+            }
+            for exe in self.executables()? {
+                files.push(exe.strip_prefix(&self.absolute_path)?.to_path_buf());
+            }
+            manifest.target = Some(target);
+            manifest.profile = Some(env.profile.as_str().to_string());
+        }
+
+        fs::create_dir_all(out_dir)?;
+        let archive_path = out_dir.join(format!("{}.tar.gz", archive_name));
+        let encoder = GzEncoder::new(fs::File::create(&archive_path)?, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let top = PathBuf::from(&base);
+
+        for rel in &files {
+            let path = self.absolute_path.join(rel);
+            let data = fs::read(&path)?;
+            manifest
+                .files
+                .insert(rel.to_string_lossy().replace('\\', "/"), sha256_hex(&data));
+            append(&mut builder, &top.join(rel), &data, file_mode(&path))?;
+        }
+        let manifest_text = serde_yaml::to_string(&manifest)?;
+        append(
+            &mut builder,
+            &top.join(MANIFEST),
+            manifest_text.as_bytes(),
+            0o644,
+        )?;
+        builder.into_inner()?.finish()?;
+
+        let checksum = sha256_file(&archive_path)?;
+        let file_name = archive_path.file_name().unwrap().to_string_lossy();
+        fs::write(
+            out_dir.join(format!("{}.sha256", file_name)),
+            format!("{}  {}\n", checksum, file_name),
+        )?;
+        logi!("Packaged {} file(s) into {:?}", files.len(), archive_path);
+        Ok(archive_path)
+    }
+}
+
+/// 以固定的时间戳与属主写入归档条目，使相同输入得到相同的归档
+fn append<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    data: &[u8],
+    mode: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+fn file_mode(path: &Path) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .map(|m| m.permissions().mode() & 0o777)
+            .unwrap_or(0o644)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        0o644
+    }
+}
+
+/// 校验归档的 sha256：优先使用声明的值，否则使用同目录的 <archive>.sha256 文件
+pub fn verify_archive(archive: &Path, expected: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let actual = sha256_file(archive)?;
+    let sidecar = PathBuf::from(format!("{}.sha256", archive.display()));
+    let expected = match expected {
+        Some(sum) => Some(sum.to_string()),
+        None => fs::read_to_string(&sidecar)
+            .ok()
+            .and_then(|text| text.split_whitespace().next().map(str::to_string)),
+    };
+    match expected {
+        Some(sum) if !sum.eq_ignore_ascii_case(&actual) => Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            archive.display(),
+            sum,
+            actual
+        )
+        .into()),
+        Some(_) => Ok(actual),
+        None => Err(format!(
+            "No checksum for {}: set `sha256` or provide {}",
+            archive.display(),
+            sidecar.display()
+        )
+        .into()),
+    }
+}
+
+/// 解压模块归档到 dest（去掉顶层目录），并按清单校验每个文件
+pub fn unpack_archive(archive: &Path, dest: &Path) -> Result<PackageManifest, Box<dyn Error>> {
+    if dest.exists() {
+        fs::remove_dir_all(dest)?;
+    }
+    fs::create_dir_all(dest)?;
+
+    let mut tar = tar::Archive::new(GzDecoder::new(fs::File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let rel: PathBuf = path.components().skip(1).collect();
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("Unsafe path in {}: {}", archive.display(), path.display()).into());
+        }
+        // 只接受普通文件与目录，符号链接、硬链接等可能指向解压目录之外
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            return Err(format!(
+                "Unsupported entry type {:?} in {}: {}",
+                kind,
+                archive.display(),
+                path.display()
+            )
+            .into());
+        }
+        let target = dest.join(&rel);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        entry.unpack(&target)?;
+    }
+
+    let manifest = PackageManifest::load(dest)?;
+    for (rel, sum) in &manifest.files {
+        let actual = sha256_file(&dest.join(rel))
+            .map_err(|e| format!("{}: missing {}: {}", archive.display(), rel, e))?;
+        if &actual != sum {
+            return Err(format!("{}: checksum mismatch for {}", archive.display(), rel).into());
+        }
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 写一个顶层目录为 evil/ 的归档，含一个指向 target 的链接条目
+    fn link_archive(path: &Path, kind: tar::EntryType, target: &Path) {
+        let file = fs::File::create(path).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_size(0);
+        header.set_mode(0o644);
+        builder
+            .append_link(&mut header, "evil/include/link", target)
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn unpack_rejects_links() {
+        let root = std::env::temp_dir().join(format!("hk-package-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let outside = root.join("outside.txt");
+        fs::write(&outside, "secret").unwrap();
+
+        for (name, kind) in [
+            ("symlink", tar::EntryType::Symlink),
+            ("hardlink", tar::EntryType::Link),
+        ] {
+            let archive = root.join(format!("{}.tar.gz", name));
+            link_archive(&archive, kind, &outside);
+            let dest = root.join(name);
+            let err = unpack_archive(&archive, &dest).unwrap_err();
+            assert!(
+                err.to_string().contains("Unsupported entry type"),
+                "{}",
+                err
+            );
+            assert!(!dest.join("include").join("link").exists());
+        }
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "secret");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+use crate::logi;
+use crate::utils::yaml::{Config, ModDep};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// hk new 的内置模板
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    /// 可执行模块：bin/main.c 打印 hello world
+    Bin,
+    /// 库模块：头文件、实现与测试
+    Lib,
+    /// 项目：库模块 core 与依赖它的可执行模块 app
+    Project,
+}
+
+const MAIN_C: &str = r#"#include <stdio.h>
+
+int main(void)
+{
+    printf("Hello from {{name}}!\n");
+    return 0;
+}
+"#;
+
+const LIB_H: &str = r#"#ifndef {{NAME}}_H
+#define {{NAME}}_H
+
+/* 返回两数之和 */
+int {{ident}}_add(int a, int b);
+
+#endif /* {{NAME}}_H */
+"#;
+
+const LIB_C: &str = r#"#include "{{name}}.h"
+
+int {{ident}}_add(int a, int b)
+{
+    return a + b;
+}
+"#;
+
+const LIB_TEST_C: &str = r#"#include <stdio.h>
+#include "{{name}}.h"
+
+int main(void)
+{
+    if ({{ident}}_add(2, 3) != 5) {
+        printf("{{ident}}_add(2, 3) != 5\n");
+        return 1;
+    }
+    return 0;
+}
+"#;
+
+const APP_MAIN_C: &str = r#"#include <stdio.h>
+#include "core.h"
+
+int main(void)
+{
+    printf("Hello from {{name}}! 2 + 3 = %d\n", core_add(2, 3));
+    return 0;
+}
+"#;
+
+/// 替换模板中的占位符：{{name}} 为模块名原样，{{ident}} 与 {{NAME}} 为转换后的
+/// 小写与大写 C 标识符（'-' 等字符替换为 '_'）
+pub fn render(text: &str, name: &str) -> String {
+    let ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    text.replace("{{name}}", name)
+        .replace("{{ident}}", &ident)
+        .replace("{{NAME}}", &ident.to_ascii_uppercase())
+}
+
+/// 模块名需能作为目录名与库文件名使用
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!(
+            "Invalid module name '{}': use letters, digits, '_' and '-', starting with a letter or '_'",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, text: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+fn write_config(dir: &Path, name: &str, mod_deps: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut config = Config::new();
+    config.name = name.to_string();
+    config.dep.mod_deps = mod_deps
+        .iter()
+        .map(|dep| ModDep {
+            path: dep.to_string(),
+            version: None,
+        })
+        .collect();
+    write_file(&dir.join("config.yaml"), &config.to_yaml()?)
+}
+
+/// 按内置模板生成模块（或项目）目录，目标目录已存在时报错
+pub fn generate(name: &str, kind: TemplateKind) -> Result<(), Box<dyn Error>> {
+    let base = PathBuf::from(name);
+    let mod_name = base
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Invalid module path: {}", name))?;
+    check_name(&mod_name)?;
+    if base.exists() {
+        return Err(format!("Destination {} already exists", base.display()).into());
+    }
+
+    match kind {
+        TemplateKind::Bin => gen_bin(&base, &mod_name)?,
+        TemplateKind::Lib => gen_lib(&base, &mod_name)?,
+        TemplateKind::Project => {
+            gen_lib(&base.join("core"), "core")?;
+            fs::create_dir_all(base.join("app").join("include"))?;
+            fs::create_dir_all(base.join("app").join("src"))?;
+            write_file(
+                &base.join("app").join("bin").join("main.c"),
+                &render(APP_MAIN_C, &mod_name),
+            )?;
+            write_config(&base.join("app"), "app", &["../core"])?;
+        }
+    }
+    logi!("Created {:?} from {:?} template", base, kind);
+    Ok(())
+}
+
+fn gen_bin(base: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(base.join("include"))?;
+    fs::create_dir_all(base.join("src"))?;
+    write_file(&base.join("bin").join("main.c"), &render(MAIN_C, name))?;
+    write_config(base, name, &[])
+}
+
+fn gen_lib(base: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(base.join("bin"))?;
+    write_file(
+        &base.join("include").join(format!("{}.h", name)),
+        &render(LIB_H, name),
+    )?;
+    write_file(
+        &base.join("src").join(format!("{}.c", name)),
+        &render(LIB_C, name),
+    )?;
+    write_file(
+        &base.join("test").join(format!("test_{}.c", name)),
+        &render(LIB_TEST_C, name),
+    )?;
+    write_config(base, name, &[])
+}
+
+/// 按名字查找用户模板目录，依次搜索：
+/// - 直接给出的目录路径
+/// - 运行目录及其上级目录（项目根目录）下的 templates/<name>
+/// - ~/.hk/templates/<name>
+pub fn find_template(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let mut candidates = vec![
+        PathBuf::from(name),
+        Path::new("templates").join(name),
+        Path::new("..").join("templates").join(name),
+    ];
+    if let Some(home) = std::env::var_os("HOME") {
+        candidates.push(PathBuf::from(home).join(".hk").join("templates").join(name));
+    }
+
+    for path in &candidates {
+        if path.is_dir() {
+            return Ok(path.clone());
+        }
+    }
+
+    let searched: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+    Err(format!(
+        "Template '{}' not found, searched: {}",
+        name,
+        searched.join(", ")
+    )
+    .into())
+}
+
+/// 复制用户模板目录生成模块：文件名与文本文件内容中的占位符均被替换
+pub fn generate_from(name: &str, template: &Path) -> Result<(), Box<dyn Error>> {
+    let base = PathBuf::from(name);
+    let mod_name = base
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Invalid module path: {}", name))?;
+    check_name(&mod_name)?;
+    if base.exists() {
+        return Err(format!("Destination {} already exists", base.display()).into());
+    }
+
+    copy_template(template, &base, &mod_name)?;
+    if !base.join("config.yaml").is_file() {
+        write_config(&base, &mod_name, &[])?;
+    }
+    logi!("Created {:?} from template {:?}", base, template);
+    Ok(())
+}
+
+fn copy_template(src: &Path, dst: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dst)?;
+    let mut entries: Vec<PathBuf> = fs::read_dir(src)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if file_name == ".git" {
+            continue;
+        }
+        let target = dst.join(render(&file_name, name));
+        if path.is_dir() {
+            copy_template(&path, &target, name)?;
+        } else {
+            let data = fs::read(&path)?;
+            match String::from_utf8(data) {
+                Ok(text) => fs::write(&target, render(&text, name))?,
+                // 非文本文件原样复制
+                Err(e) => fs::write(&target, e.into_bytes())?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 将已有的 C 源码目录转换为模块：根目录下的 .h 移入 include/，
+/// 含 main 函数的 .c 移入 bin/，其余 .c 移入 src/，并生成 config.yaml
+pub fn init(dir: &Path, name: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if dir.join("config.yaml").exists() {
+        return Err(format!("{} is already a module (config.yaml exists)", dir.display()).into());
+    }
+    let mod_name = match name {
+        Some(name) => name.to_string(),
+        None => fs::canonicalize(dir)?
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or("Cannot infer module name, pass --name")?,
+    };
+    check_name(&mod_name)?;
+
+    // 先确定所有文件的去向并检查冲突，出错时不移动任何文件
+    for sub in ["bin", "include", "src"] {
+        let sub_dir = dir.join(sub);
+        if sub_dir.exists() && !sub_dir.is_dir() {
+            return Err(format!("{} exists and is not a directory", sub_dir.display()).into());
+        }
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .collect();
+    entries.sort();
+    let mut moves = Vec::new();
+    for path in entries {
+        let sub = match path.extension().and_then(|e| e.to_str()) {
+            Some("h") => "include",
+            Some("c") => {
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                if has_main(&text) {
+                    "bin"
+                } else {
+                    "src"
+                }
+            }
+            Some("s") | Some("S") => "src",
+            _ => continue,
+        };
+        let target = dir.join(sub).join(path.file_name().unwrap_or_default());
+        if target.exists() {
+            return Err(format!(
+                "Cannot move {}: {} already exists",
+                path.display(),
+                target.display()
+            )
+            .into());
+        }
+        moves.push((path, target));
+    }
+
+    for sub in ["bin", "include", "src"] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+    for (path, target) in moves {
+        fs::rename(&path, &target)?;
+        logi!("Moved {:?} -> {:?}", path, target);
+    }
+    write_config(dir, &mod_name, &[])?;
+    logi!("Initialized module {} in {:?}", mod_name, dir);
+    Ok(())
+}
+
+/// 粗略判断源码是否定义了 main 函数：形如 `int main(` 的行
+fn has_main(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim_start();
+        let Some(rest) = line.strip_prefix("int") else {
+            return false;
+        };
+        rest.starts_with(char::is_whitespace)
+            && rest
+                .trim_start()
+                .strip_prefix("main")
+                .is_some_and(|r| r.trim_start().starts_with('('))
+    })
+}
@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::sync::Mutex;
+
+/// gcc 输出 JSON 诊断的参数，仅在 --message-format json 且探测支持时使用
+pub const JSON_FLAG: &str = "-fdiagnostics-format=json";
+
+/// 构建消息的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// 编译器原始文本（已去重）
+    Human,
+    /// 每行一个 JSON 对象，输出到 stdout，其余信息改到 stderr
+    Json,
+}
+
+impl MessageFormat {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!("Unknown message format: {} (expected human or json)", value).into()),
+        }
+    }
+}
+
+/// 一条编译器诊断
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// error / warning / note
+    pub severity: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+    /// 控制该诊断的选项，如 -Wunused-variable
+    pub option: Option<String>,
+    /// 附属的 note
+    pub children: Vec<Diagnostic>,
+    /// 编译器输出的完整文本（含上下文、源码行与 note）
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    /// 去重用的键：位置、级别与消息相同即视为同一诊断
+    fn key(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            self.file.as_deref().unwrap_or_default(),
+            self.line.unwrap_or_default(),
+            self.column.unwrap_or_default(),
+            self.severity,
+            self.message
+        )
+    }
+
+    /// 形如 `file:line:col: severity: message [option]` 的一行
+    fn head(&self) -> String {
+        let mut line = String::new();
+        if let Some(file) = &self.file {
+            line.push_str(file);
+            for n in [self.line, self.column].into_iter().flatten() {
+                line.push_str(&format!(":{}", n));
+            }
+            line.push_str(": ");
+        }
+        line.push_str(&format!("{}: {}", self.severity, self.message));
+        if let Some(option) = &self.option {
+            line.push_str(&format!(" [{}]", option));
+        }
+        line
+    }
+}
+
+/// 解析一行诊断头，如 `src/a.c:3:7: warning: unused variable 'x' [-Wunused-variable]`
+fn parse_head(line: &str) -> Option<Diagnostic> {
+    let (pos, severity, pat_len) = ["fatal error", "error", "warning", "note"]
+        .iter()
+        .filter_map(|sev| {
+            let pat = format!(": {}: ", sev);
+            line.find(&pat).map(|pos| (pos, *sev, pat.len()))
+        })
+        .min_by_key(|(pos, _, _)| *pos)?;
+
+    // 位置可能是 file:line:col、file:line 或只有工具名（如 ld、collect2）
+    let location = &line[..pos];
+    let mut parts = location.rsplitn(3, ':');
+    let last = parts.next().and_then(|s| s.parse::<u32>().ok());
+    let second = parts.next();
+    let (file, line_no, column) = match (last, second.and_then(|s| s.parse::<u32>().ok())) {
+        (Some(col), Some(l)) => (parts.next().map(str::to_string), Some(l), Some(col)),
+        (Some(l), None) => (
+            location.rsplit_once(':').map(|(f, _)| f.to_string()),
+            Some(l),
+            None,
+        ),
+        _ => (Some(location.to_string()), None, None),
+    };
+
+    let mut message = line[pos + pat_len..].to_string();
+    let mut option = None;
+    if message.ends_with(']') {
+        if let Some(start) = message.rfind(" [-W") {
+            option = Some(message[start + 2..message.len() - 1].to_string());
+            message.truncate(start);
+        }
+    }
+
+    Some(Diagnostic {
+        severity: if severity == "fatal error" {
+            "error".to_string()
+        } else {
+            severity.to_string()
+        },
+        file,
+        line: line_no,
+        column,
+        message,
+        option,
+        children: Vec::new(),
+        rendered: String::new(),
+    })
+}
+
+/// 诊断前的上下文行，如 `In file included from ...`、`a.c: In function 'main':`
+fn is_context(line: &str) -> bool {
+    line.starts_with("In file included from ")
+        || (line.starts_with(' ') && line.trim_start().starts_with("from "))
+        || (line.ends_with(':') && (line.contains(": In ") || line.contains(": At ")))
+}
+
+/// 解析 gcc/clang 的文本诊断，返回诊断列表与无法归入任何诊断的其余输出
+pub fn parse_text(text: &str) -> (Vec<Diagnostic>, String) {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut context = String::new();
+    let mut other = String::new();
+
+    for line in text.lines() {
+        if is_context(line) {
+            context.push_str(line);
+            context.push('\n');
+            continue;
+        }
+        match parse_head(line) {
+            // note 归入前一条诊断
+            Some(note) if note.severity == "note" && !diagnostics.is_empty() => {
+                let last = diagnostics.last_mut().unwrap();
+                last.rendered.push_str(&std::mem::take(&mut context));
+                last.rendered.push_str(line);
+                last.rendered.push('\n');
+                last.children.push(note);
+            }
+            Some(mut diag) => {
+                diag.rendered = std::mem::take(&mut context);
+                diag.rendered.push_str(line);
+                diag.rendered.push('\n');
+                diagnostics.push(diag);
+            }
+            // 源码行、插入符等跟随前一条诊断
+            None => {
+                let target = match diagnostics.last_mut() {
+                    Some(last) => &mut last.rendered,
+                    None => &mut other,
+                };
+                target.push_str(&std::mem::take(&mut context));
+                target.push_str(line);
+                target.push('\n');
+            }
+        }
+    }
+    other.push_str(&context);
+    (diagnostics, other)
+}
+
+#[derive(Deserialize)]
+struct GccDiagnostic {
+    kind: String,
+    message: String,
+    #[serde(default)]
+    option: Option<String>,
+    #[serde(default)]
+    locations: Vec<GccLocation>,
+    #[serde(default)]
+    children: Vec<GccDiagnostic>,
+}
+
+#[derive(Deserialize)]
+struct GccLocation {
+    caret: GccPosition,
+}
+
+#[derive(Deserialize)]
+struct GccPosition {
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl GccDiagnostic {
+    fn into_diagnostic(self) -> Diagnostic {
+        let caret = self.locations.into_iter().next().map(|l| l.caret);
+        let mut diag = Diagnostic {
+            severity: match self.kind.as_str() {
+                "fatal error" => "error".to_string(),
+                kind => kind.to_string(),
+            },
+            file: caret.as_ref().map(|c| c.file.clone()),
+            line: caret.as_ref().map(|c| c.line),
+            column: caret.as_ref().map(|c| c.column),
+            message: self.message,
+            option: self.option,
+            children: self
+                .children
+                .into_iter()
+                .map(GccDiagnostic::into_diagnostic)
+                .collect(),
+            rendered: String::new(),
+        };
+        diag.rendered = std::iter::once(&diag)
+            .chain(&diag.children)
+            .map(|d| d.head() + "\n")
+            .collect();
+        diag
+    }
+}
+
+/// 解析 gcc -fdiagnostics-format=json 的输出（一个或多个 JSON 数组），
+/// 无法解析的部分按文本格式处理
+pub fn parse_json(text: &str) -> (Vec<Diagnostic>, String) {
+    let mut diagnostics = Vec::new();
+    let mut rest = String::new();
+    for line in text.lines() {
+        match serde_json::from_str::<Vec<GccDiagnostic>>(line) {
+            Ok(list) => diagnostics.extend(list.into_iter().map(GccDiagnostic::into_diagnostic)),
+            Err(_) => {
+                rest.push_str(line);
+                rest.push('\n');
+            }
+        }
+    }
+    let (more, other) = parse_text(&rest);
+    diagnostics.extend(more);
+    (diagnostics, other)
+}
+
+/// 一次构建中已输出的诊断：跨翻译单元去重，并按模块统计警告与错误数
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    seen: Mutex<HashSet<String>>,
+    warnings: Mutex<BTreeMap<String, usize>>,
+    errors: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 过滤掉已输出过的诊断，新出现的警告与错误计入 module
+    pub fn collect(&self, module: &str, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let mut seen = self.seen.lock().unwrap();
+        let fresh: Vec<Diagnostic> = diagnostics
+            .into_iter()
+            .filter(|d| seen.insert(d.key()))
+            .collect();
+        for (severity, counts) in [("warning", &self.warnings), ("error", &self.errors)] {
+            let count = fresh.iter().filter(|d| d.severity == severity).count();
+            if count > 0 {
+                *counts
+                    .lock()
+                    .unwrap()
+                    .entry(module.to_string())
+                    .or_default() += count;
+            }
+        }
+        fresh
+    }
+
+    pub fn warnings(&self, module: &str) -> usize {
+        self.warnings
+            .lock()
+            .unwrap()
+            .get(module)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 各模块的警告数
+    pub fn warning_counts(&self) -> BTreeMap<String, usize> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// 各模块的错误数
+    pub fn error_counts(&self) -> BTreeMap<String, usize> {
+        self.errors.lock().unwrap().clone()
+    }
+}
+
+/// 以一行 JSON 输出到 stdout
+pub fn emit_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
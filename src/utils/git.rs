@@ -0,0 +1,81 @@
+use crate::logd;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// 执行 git 命令并返回标准输出，失败时带上 git 的错误输出
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    cmd.args(args).stdin(Stdio::null());
+    logd!("Running: {:?}", cmd);
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 克隆仓库到 dest，url 来自依赖配置，以 -- 分隔避免被当作选项
+pub fn clone(url: &str, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let dest = dest.to_string_lossy();
+    git(None, &["clone", "--quiet", "--", url, &dest])?;
+    Ok(())
+}
+
+/// 从 origin 拉取分支与标签
+pub fn fetch(dir: &Path) -> Result<(), Box<dyn Error>> {
+    git(Some(dir), &["fetch", "--quiet", "--tags", "origin"])?;
+    Ok(())
+}
+
+/// 检查来自依赖配置或锁文件的版本名，以 - 开头的会被 git 当作选项
+pub fn check_rev(rev: &str) -> Result<(), Box<dyn Error>> {
+    if rev.is_empty() || rev.starts_with('-') {
+        return Err(format!("Invalid git revision '{}'", rev).into());
+    }
+    Ok(())
+}
+
+/// 将分支、标签或提交解析为完整的提交哈希，不存在时返回 None
+pub fn rev_parse(dir: &Path, rev: &str) -> Option<String> {
+    let spec = format!("{}^{{commit}}", rev);
+    git(Some(dir), &["rev-parse", "--verify", "--quiet", &spec]).ok()
+}
+
+/// 当前检出的提交
+pub fn head(dir: &Path) -> Option<String> {
+    rev_parse(dir, "HEAD")
+}
+
+/// 相对 base 有改动的文件（含未提交的修改与未跟踪的文件），返回绝对路径
+pub fn changed_files(dir: &Path, base: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let top = PathBuf::from(git(Some(dir), &["rev-parse", "--show-toplevel"])?);
+    let diff = git(Some(dir), &["diff", "--name-only", base, "--"])?;
+    let untracked = git(
+        Some(dir),
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    )?;
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .map(|line| top.join(line))
+        .collect())
+}
+
+/// 以分离头指针方式检出提交
+pub fn checkout(dir: &Path, commit: &str) -> Result<(), Box<dyn Error>> {
+    check_rev(commit)?;
+    git(Some(dir), &["checkout", "--quiet", "--detach", commit])?;
+    Ok(())
+}
@@ -1,39 +1,178 @@
-#[cfg(debug_assertions)]
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// 日志级别，数值越大越详细
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// 终端输出的默认级别，0 表示关闭
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+/// 按子系统（模块路径的最后一段，如 git、mod_file）单独设置的级别
+static TARGETS: OnceLock<Vec<(String, u8)>> = OnceLock::new();
+/// 完整日志文件，记录所有级别
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// 初始化日志：verbose 为 -v 的个数，quiet 只输出错误；
+/// HK_LOG 形如 `debug` 或 `info,git=debug,mod_file=trace`，其中的默认级别会被 -v/-q 覆盖
+pub fn init(
+    verbose: u8,
+    quiet: bool,
+    log_file: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    START.get_or_init(Instant::now);
+
+    let mut level = Level::Warn as u8;
+    let mut targets = Vec::new();
+    if let Ok(spec) = std::env::var("HK_LOG") {
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (target, name) = match part.split_once('=') {
+                Some((target, name)) => (Some(target.trim()), name.trim()),
+                None => (None, part),
+            };
+            let value = match name {
+                "off" => 0,
+                _ => Level::parse(name)
+                    .ok_or_else(|| format!("Invalid log level '{}' in HK_LOG", name))?
+                    as u8,
+            };
+            match target {
+                Some(target) => targets.push((target.to_string(), value)),
+                None => level = value,
+            }
+        }
+    }
+    if quiet {
+        level = Level::Error as u8;
+    } else if verbose > 0 {
+        level = (Level::Info as u8)
+            .saturating_add(verbose - 1)
+            .min(Level::Trace as u8);
+    }
+    LEVEL.store(level, Ordering::Relaxed);
+    let _ = TARGETS.set(targets);
+
+    if let Some(path) = log_file {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create log file {}: {}", path.display(), e))?;
+        *FILE.lock().unwrap() = Some(file);
+    }
+    Ok(())
+}
+
+/// 子系统名：模块路径的最后一段
+fn target_name(module_path: &str) -> &str {
+    module_path.rsplit("::").next().unwrap_or(module_path)
+}
+
+fn console_enabled(level: Level, target: &str) -> bool {
+    let max = TARGETS
+        .get()
+        .and_then(|targets| targets.iter().rev().find(|(t, _)| t == target))
+        .map_or_else(|| LEVEL.load(Ordering::Relaxed), |(_, l)| *l);
+    level as u8 <= max
+}
+
+/// 判断某条日志是否需要输出（终端或日志文件）
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    console_enabled(level, target_name(module_path)) || FILE.lock().unwrap().is_some()
+}
+
+pub fn log(level: Level, module_path: &str, args: std::fmt::Arguments) {
+    let target = target_name(module_path);
+    let elapsed = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let line = format!(
+        "[{:>8.3}s {:<5} {}] {}",
+        elapsed,
+        level.as_str(),
+        target,
+        args
+    );
+    if console_enabled(level, target) {
+        eprintln!("{}", line);
+    }
+    if let Some(file) = FILE.lock().unwrap().as_mut() {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
 #[macro_export]
 macro_rules! loge {
     ($($arg:tt)*) => {
-        println!("[Error]: {}:{} {}", file!(), line!(), format!($($arg)*));
+        $crate::log_at!($crate::utils::log::Level::Error, $($arg)*)
     };
 }
-#[cfg(not(debug_assertions))]
+
 #[macro_export]
-macro_rules! loge {
-    ($($arg:tt)*) => {};
+macro_rules! logw {
+    ($($arg:tt)*) => {
+        $crate::log_at!($crate::utils::log::Level::Warn, $($arg)*)
+    };
 }
 
-#[cfg(debug_assertions)]
 #[macro_export]
-macro_rules! logd {
+macro_rules! logi {
     ($($arg:tt)*) => {
-        eprintln!("[Debug]: {}:{} {}", file!(), line!(), format_args!($($arg)*));
+        $crate::log_at!($crate::utils::log::Level::Info, $($arg)*)
     };
 }
 
-/// Debug 日志的“空”版本：release 模式下不产生任何代码
-#[cfg(not(debug_assertions))]
 #[macro_export]
 macro_rules! logd {
-    ($($arg:tt)*) => {};
+    ($($arg:tt)*) => {
+        $crate::log_at!($crate::utils::log::Level::Debug, $($arg)*)
+    };
 }
-#[cfg(debug_assertions)]
+
 #[macro_export]
-macro_rules! logi {
+macro_rules! logt {
     ($($arg:tt)*) => {
-        println!("[Info]: {}:{} {}", file!(), line!(), format!($($arg)*));
+        $crate::log_at!($crate::utils::log::Level::Trace, $($arg)*)
     };
 }
-#[cfg(not(debug_assertions))]
+
+/// 按级别输出日志，未启用时不格式化参数
 #[macro_export]
-macro_rules! logi {
-    ($($arg:tt)*) => {};
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::utils::log::enabled($level, module_path!()) {
+            $crate::utils::log::log($level, module_path!(), format_args!($($arg)*));
+        }
+    };
 }
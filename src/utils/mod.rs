@@ -1,3 +1,13 @@
+pub mod diagnostics;
+pub mod git;
 pub mod log;
 pub mod logo;
-pub mod yaml;
\ No newline at end of file
+pub mod pattern;
+pub mod pkg_config;
+pub mod probe;
+pub mod progress;
+pub mod shell;
+pub mod timings;
+pub mod toolchain;
+pub mod yaml;
+pub mod yaml_edit;
@@ -0,0 +1,100 @@
+use crate::logd;
+use crate::utils::shell;
+use std::error::Error;
+use std::process::Command;
+
+/// 通过 pkg-config 解析出的编译与链接参数
+#[derive(Debug, Clone, Default)]
+pub struct PkgFlags {
+    pub cflags: Vec<String>,
+    pub libs: Vec<String>,
+}
+
+/// pkg-config 程序名，可用 PKG_CONFIG 环境变量覆盖
+fn pkg_config_bin() -> String {
+    std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string())
+}
+
+/// 解析一组包规格（如 `zlib`、`openssl >= 1.1`），缺失或版本不满足时返回明确的错误
+pub fn resolve(specs: &[String], sysroot: Option<&str>) -> Result<PkgFlags, Box<dyn Error>> {
+    let mut flags = PkgFlags::default();
+    for spec in specs {
+        check_exists(spec, sysroot)?;
+        for arg in query(spec, "--cflags", sysroot)? {
+            if !flags.cflags.contains(&arg) {
+                flags.cflags.push(arg);
+            }
+        }
+        flags.libs.extend(query(spec, "--libs", sysroot)?);
+    }
+    Ok(flags)
+}
+
+fn command(sysroot: Option<&str>) -> Command {
+    let mut cmd = Command::new(pkg_config_bin());
+    // 交叉编译时让 pkg-config 返回 sysroot 内的路径
+    if let Some(sysroot) = sysroot {
+        if std::env::var_os("PKG_CONFIG_SYSROOT_DIR").is_none() {
+            cmd.env("PKG_CONFIG_SYSROOT_DIR", sysroot);
+        }
+    }
+    cmd
+}
+
+/// 包名（规格中的第一个词）
+fn package_name(spec: &str) -> &str {
+    spec.split_whitespace().next().unwrap_or(spec)
+}
+
+fn check_exists(spec: &str, sysroot: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let output = command(sysroot)
+        .arg("--print-errors")
+        .arg("--exists")
+        .arg(spec)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", pkg_config_bin(), e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    // 包存在但版本不满足时给出实际版本
+    let name = package_name(spec);
+    let found = command(sysroot)
+        .arg("--modversion")
+        .arg(name)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    let msg = match found {
+        Some(version) => format!(
+            "pkg-config package '{}' found version {}, but '{}' is required",
+            name, version, spec
+        ),
+        None => format!(
+            "pkg-config package '{}' not found: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    };
+    Err(msg.into())
+}
+
+fn query(spec: &str, what: &str, sysroot: Option<&str>) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut cmd = command(sysroot);
+    cmd.arg(what).arg(spec);
+    logd!("Querying: {:?}", cmd);
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "pkg-config {} {} failed: {}",
+            what,
+            spec,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(shell::split(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    )?)
+}
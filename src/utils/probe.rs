@@ -0,0 +1,241 @@
+use crate::logd;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::UNIX_EPOCH,
+};
+
+/// 编译器种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerKind {
+    Gcc,
+    Clang,
+    Other,
+}
+
+impl CompilerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompilerKind::Gcc => "gcc",
+            CompilerKind::Clang => "clang",
+            CompilerKind::Other => "unknown",
+        }
+    }
+}
+
+/// 编译器探测结果
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompilerInfo {
+    /// 配置中的编译器名，如 gcc / arm-none-eabi-gcc
+    pub cc: String,
+    /// 在 PATH 中定位到的绝对路径，未找到时为空
+    pub path: Option<PathBuf>,
+    pub kind: CompilerKind,
+    pub version: String,
+    /// 编译器文件的修改时间，用于判断缓存是否失效
+    pub mtime: u64,
+    /// 探测时附加的目标参数（--target / --sysroot）
+    #[serde(default)]
+    pub target_args: Vec<String>,
+    /// 已探测的 -std 取值及是否支持
+    #[serde(default)]
+    pub stds: BTreeMap<String, bool>,
+    /// 已探测的参数及是否支持
+    #[serde(default)]
+    pub flags: BTreeMap<String, bool>,
+}
+
+impl CompilerInfo {
+    pub fn found(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// 探测编译器：定位路径、识别种类与版本
+    fn detect(cc: &str, target_args: &[String]) -> Self {
+        let path = find_program(cc);
+        let mtime = path.as_deref().map(file_mtime).unwrap_or(0);
+        let mut info = CompilerInfo {
+            cc: cc.to_string(),
+            path,
+            kind: CompilerKind::Other,
+            version: String::new(),
+            mtime,
+            target_args: target_args.to_vec(),
+            stds: BTreeMap::new(),
+            flags: BTreeMap::new(),
+        };
+        let Some(path) = info.path.clone() else {
+            return info;
+        };
+
+        let banner = capture(Command::new(&path).arg("--version")).unwrap_or_default();
+        let banner = banner.to_lowercase();
+        info.kind = if banner.contains("clang") {
+            CompilerKind::Clang
+        } else if banner.contains("gcc") || banner.contains("free software foundation") {
+            CompilerKind::Gcc
+        } else {
+            CompilerKind::Other
+        };
+
+        // gcc 的 -dumpversion 可能只有主版本号，优先使用 -dumpfullversion
+        info.version = capture(Command::new(&path).arg("-dumpfullversion"))
+            .or_else(|| capture(Command::new(&path).arg("-dumpversion")))
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default();
+        info
+    }
+
+    /// 检查是否支持 -std=<std>，结果写入缓存
+    pub fn supports_std(&mut self, std: &str) -> bool {
+        if let Some(&ok) = self.stds.get(std) {
+            return ok;
+        }
+        let ok = self.try_compile(&[format!("-std={}", std)]);
+        self.stds.insert(std.to_string(), ok);
+        ok
+    }
+
+    /// 检查是否支持某个编译参数，结果写入缓存
+    pub fn supports_flag(&mut self, flag: &str) -> bool {
+        if let Some(&ok) = self.flags.get(flag) {
+            return ok;
+        }
+        let ok = self.try_compile(&[flag.to_string()]);
+        self.flags.insert(flag.to_string(), ok);
+        ok
+    }
+
+    /// 用给定参数对空输入做语法检查，以退出码判断参数是否被接受
+    fn try_compile(&self, args: &[String]) -> bool {
+        let Some(path) = &self.path else {
+            return false;
+        };
+        let mut cmd = Command::new(path);
+        cmd.args(&self.target_args)
+            .args(args)
+            .args(["-x", "c", "-fsyntax-only", "-Werror", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        logd!("Probing: {:?}", cmd);
+        cmd.status().map(|s| s.success()).unwrap_or(false)
+    }
+}
+
+/// 判断参数是否为需要探测支持情况的编译能力参数（-f/-m/-W，链接参数除外）
+pub fn is_probeable_flag(flag: &str) -> bool {
+    (flag.starts_with("-f") || flag.starts_with("-m") || flag.starts_with("-W"))
+        && !flag.starts_with("-Wl,")
+        && !flag.starts_with("-Wa,")
+        && !flag.starts_with("-Wp,")
+}
+
+/// 项目级探测缓存，保存在 <project>/.hk/probe.yaml
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ProbeCache {
+    #[serde(default)]
+    pub compilers: BTreeMap<String, CompilerInfo>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl ProbeCache {
+    /// 加载项目缓存，不存在或损坏时返回空缓存
+    pub fn load(project_root: &Path) -> Self {
+        let path = project_root.join(".hk").join("probe.yaml");
+        let mut cache: ProbeCache = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_yaml::from_str(&text).ok())
+            .unwrap_or_default();
+        cache.path = path;
+        cache
+    }
+
+    /// 获取编译器在给定目标参数下的信息：缓存命中且编译器文件未变化时直接复用，否则重新探测
+    pub fn compiler(&mut self, cc: &str, target_args: &[String]) -> &mut CompilerInfo {
+        let key = probe_key(cc, target_args);
+        let fresh = match self.compilers.get(&key) {
+            Some(info) => {
+                let path = find_program(cc);
+                info.path == path && path.as_deref().map(file_mtime).unwrap_or(0) == info.mtime
+            }
+            None => false,
+        };
+        if !fresh {
+            self.compilers
+                .insert(key.clone(), CompilerInfo::detect(cc, target_args));
+        }
+        self.compilers.get_mut(&key).unwrap()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// 探测缓存的键：编译器名，有目标参数时追加在后面
+pub fn probe_key(cc: &str, target_args: &[String]) -> String {
+    if target_args.is_empty() {
+        cc.to_string()
+    } else {
+        format!("{} {}", cc, target_args.join(" "))
+    }
+}
+
+/// 在 PATH 中查找程序；包含路径分隔符时按路径直接判断
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    let direct = Path::new(name);
+    if direct.components().count() > 1 {
+        return direct.is_file().then(|| direct.to_path_buf());
+    }
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+fn file_mtime(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 执行命令并返回标准输出，失败时返回 None
+fn capture(cmd: &mut Command) -> Option<String> {
+    let output = cmd
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
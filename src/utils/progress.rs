@@ -0,0 +1,113 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// 计入进度的构建步骤
+const COUNTED_STEPS: [&str; 4] = ["Compiling", "Archiving", "Linking", "Linting"];
+
+/// 构建进度显示：终端中原地刷新一行 `[37/212] Compiling net/src/socket.c`，
+/// 输出不是终端时退化为逐行输出
+#[derive(Debug)]
+pub struct Progress {
+    total: AtomicUsize,
+    done: AtomicUsize,
+    tty: AtomicBool,
+    enabled: AtomicBool,
+    /// 输出到 stderr（--message-format json 时 stdout 只留给 JSON 消息）
+    stderr: AtomicBool,
+    /// 终端中是否有尚未换行的进度行
+    pending: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress {
+            total: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            tty: AtomicBool::new(std::io::stdout().is_terminal()),
+            enabled: AtomicBool::new(true),
+            stderr: AtomicBool::new(false),
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// 开始新一轮构建，total 为计入进度的步骤总数
+    pub fn start(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+    }
+
+    /// 关闭进度行（如 dry-run 只输出命令）
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Relaxed);
+    }
+
+    /// 改为输出到 stderr
+    pub fn use_stderr(&self) {
+        self.stderr.store(true, Ordering::Relaxed);
+        self.tty
+            .store(std::io::stderr().is_terminal(), Ordering::Relaxed);
+    }
+
+    fn write(&self, text: &str) {
+        if self.stderr.load(Ordering::Relaxed) {
+            let mut out = std::io::stderr().lock();
+            let _ = write!(out, "{}", text);
+            let _ = out.flush();
+        } else {
+            let mut out = std::io::stdout().lock();
+            let _ = write!(out, "{}", text);
+            let _ = out.flush();
+        }
+    }
+
+    /// 显示一个构建步骤
+    pub fn step(&self, step: &str, what: &str) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let done = if COUNTED_STEPS.contains(&step) {
+            self.done.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.done.load(Ordering::Relaxed)
+        };
+        let total = self.total.load(Ordering::Relaxed).max(done);
+        let line = format!("[{}/{}] {} {}", done, total, step, what);
+
+        if self.tty.load(Ordering::Relaxed) {
+            self.write(&format!("\r\x1b[2K{}", line));
+            self.pending.store(true, Ordering::Relaxed);
+        } else {
+            self.write(&format!("{}\n", line));
+        }
+    }
+
+    /// 清除终端中未换行的进度行，之后的输出从行首开始
+    pub fn clear(&self) {
+        if self.pending.swap(false, Ordering::Relaxed) {
+            self.write("\r\x1b[2K");
+        }
+    }
+
+    /// 输出一行普通信息，不与进度行混在一起
+    pub fn println(&self, line: &str) {
+        self.clear();
+        self.write(&format!("{}\n", line));
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 格式化耗时，如 0.42s、1m03s
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs_f64();
+    if secs < 60.0 {
+        format!("{:.2}s", secs)
+    } else {
+        format!("{}m{:02}s", d.as_secs() / 60, d.as_secs() % 60)
+    }
+}
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 一段计时：构建步骤、模块或一轮调度
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    /// step / module / schedule
+    pub cat: &'static str,
+    /// 所属模块，调度轮次为空
+    pub module: String,
+    pub start: Duration,
+    pub dur: Duration,
+    pub ok: bool,
+}
+
+/// --timings 的计时记录，未启用时不记录任何内容
+#[derive(Debug)]
+pub struct Timings {
+    enabled: AtomicBool,
+    origin: Instant,
+    spans: Mutex<Vec<Span>>,
+}
+
+/// Chrome trace-event 格式的完整事件（ph = "X"）
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'static str,
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    tid: u32,
+    args: TraceArgs<'a>,
+}
+
+#[derive(Serialize)]
+struct TraceArgs<'a> {
+    module: &'a str,
+    ok: bool,
+}
+
+#[derive(Serialize)]
+struct Trace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<TraceEvent<'a>>,
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings {
+            enabled: AtomicBool::new(false),
+            origin: Instant::now(),
+            spans: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 记录从 start 到现在的一段计时
+    pub fn record(&self, cat: &'static str, name: &str, module: &str, start: Instant, ok: bool) {
+        if !self.enabled() {
+            return;
+        }
+        let span = Span {
+            name: name.to_string(),
+            cat,
+            module: module.to_string(),
+            start: start.saturating_duration_since(self.origin),
+            dur: start.elapsed(),
+            ok,
+        };
+        self.spans.lock().unwrap().push(span);
+    }
+
+    /// 写出 Chrome trace（可在 chrome://tracing 或 Perfetto 中打开）与 HTML 汇总，返回 HTML 路径
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let spans = self.spans.lock().unwrap().clone();
+        fs::create_dir_all(dir)?;
+
+        // 调度轮次、模块与步骤分别放在不同的轨道上
+        let events = spans
+            .iter()
+            .map(|s| TraceEvent {
+                name: &s.name,
+                cat: s.cat,
+                ph: "X",
+                ts: s.start.as_micros(),
+                dur: s.dur.as_micros(),
+                pid: 1,
+                tid: match s.cat {
+                    "schedule" => 1,
+                    "module" => 2,
+                    _ => 3,
+                },
+                args: TraceArgs {
+                    module: &s.module,
+                    ok: s.ok,
+                },
+            })
+            .collect();
+        let trace = Trace {
+            trace_events: events,
+            display_time_unit: "ms",
+        };
+        fs::write(dir.join("hk-timings.json"), serde_json::to_string(&trace)?)?;
+
+        let html = dir.join("hk-timings.html");
+        fs::write(&html, render_html(&spans))?;
+        Ok(html)
+    }
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.1}", d.as_secs_f64() * 1000.0)
+}
+
+/// HTML 汇总：总耗时、调度轮次、按耗时排序的模块与最慢的步骤
+fn render_html(spans: &[Span]) -> String {
+    let total = spans
+        .iter()
+        .map(|s| s.start + s.dur)
+        .max()
+        .unwrap_or_default();
+    let scale = total.as_secs_f64().max(f64::EPSILON);
+
+    // 调度轮次按时间顺序，模块与步骤按耗时从大到小
+    let rows = |cat: &str, limit: usize| {
+        let mut list: Vec<&Span> = spans.iter().filter(|s| s.cat == cat).collect();
+        if cat != "schedule" {
+            list.sort_by_key(|s| Reverse(s.dur));
+        }
+        list.iter()
+            .take(limit)
+            .map(|s| {
+                format!(
+                    "<tr{}><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+                     <td><div class=\"bar\" style=\"margin-left:{:.1}%;width:{:.1}%\"></div></td></tr>\n",
+                    if s.ok { "" } else { " class=\"failed\"" },
+                    escape(&s.name),
+                    escape(&s.module),
+                    ms(s.start),
+                    ms(s.dur),
+                    s.start.as_secs_f64() / scale * 100.0,
+                    (s.dur.as_secs_f64() / scale * 100.0).max(0.2),
+                )
+            })
+            .collect::<String>()
+    };
+    let schedule = rows("schedule", usize::MAX);
+    let modules = rows("module", usize::MAX);
+    let steps = rows("step", 50);
+    let step_count = spans.iter().filter(|s| s.cat == "step").count();
+
+    let table = |title: &str, body: &str| {
+        format!(
+            "<h2>{}</h2>\n<table>\n<tr><th>name</th><th>module</th><th>start (ms)</th>\
+             <th>duration (ms)</th><th class=\"timeline\">timeline</th></tr>\n{}</table>\n",
+            title, body
+        )
+    };
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>hk build timings</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         table {{ border-collapse: collapse; width: 100%; margin-bottom: 2em; }}\n\
+         th, td {{ border-bottom: 1px solid #ddd; padding: 4px 8px; text-align: left; }}\n\
+         td.num {{ text-align: right; font-family: monospace; }}\n\
+         th.timeline {{ width: 40%; }}\n\
+         .bar {{ height: 10px; background: #4a90d9; }}\n\
+         tr.failed td {{ color: #c00; }}\n\
+         tr.failed .bar {{ background: #c00; }}\n\
+         </style>\n</head>\n<body>\n\
+         <h1>hk build timings</h1>\n\
+         <p>Total: {} ms, {} step(s). Open hk-timings.json in chrome://tracing or Perfetto for the full trace.</p>\n\
+         {}{}{}</body>\n</html>\n",
+        ms(total),
+        step_count,
+        table("Scheduling rounds", &schedule),
+        table("Modules", &modules),
+        table("Slowest steps", &steps),
+    )
+}
@@ -0,0 +1,144 @@
+use crate::utils::yaml::Compiler;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+/// 可复用的工具链定义文件（toolchains/<name>.yaml），
+/// 通过 `hk build --toolchain <name>` 统一应用到所有模块
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Toolchain {
+    /// 工具链名，默认取文件名
+    #[serde(default)]
+    pub name: String,
+    /// 编译器，如 arm-none-eabi-gcc
+    #[serde(default)]
+    pub cc: Option<String>,
+    /// 归档工具，如 arm-none-eabi-ar
+    #[serde(default)]
+    pub ar: Option<String>,
+    /// 链接器驱动
+    #[serde(default)]
+    pub linker: Option<String>,
+    /// 目标三元组
+    #[serde(default)]
+    pub target: Option<String>,
+    /// sysroot 路径
+    #[serde(default)]
+    pub sysroot: Option<String>,
+    /// 默认编译参数，放在模块自身 flags 之前
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// 默认链接参数，放在模块自身 ldflags 之前
+    #[serde(default)]
+    pub ldflags: Vec<String>,
+    /// objcopy 工具
+    #[serde(default)]
+    pub objcopy: Option<String>,
+    /// size 工具
+    #[serde(default)]
+    pub size: Option<String>,
+    /// 覆盖率工具，如 llvm-cov-17
+    #[serde(default)]
+    pub gcov: Option<String>,
+    /// 运行产物所用的模拟器，如 qemu-aarch64 -L $SYSROOT
+    #[serde(default)]
+    pub runner: Option<String>,
+}
+
+impl Toolchain {
+    /// 按名字查找工具链文件，依次搜索：
+    /// - 直接给出的文件路径
+    /// - 运行目录及其上级目录（项目根目录）下的 toolchains/<name>.yaml
+    /// - ~/.hk/toolchains/<name>.yaml
+    pub fn find(name: &str) -> Result<Self, Box<dyn Error>> {
+        let direct = PathBuf::from(name);
+        let mut candidates = Vec::new();
+        if direct
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            candidates.push(direct);
+        }
+
+        let file_name = format!("{}.yaml", name);
+        candidates.push(Path::new("toolchains").join(&file_name));
+        candidates.push(Path::new("..").join("toolchains").join(&file_name));
+        if let Some(home) = std::env::var_os("HOME") {
+            candidates.push(
+                PathBuf::from(home)
+                    .join(".hk")
+                    .join("toolchains")
+                    .join(&file_name),
+            );
+        }
+
+        for path in &candidates {
+            if path.is_file() {
+                return Self::from_yaml(path);
+            }
+        }
+
+        let searched: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+        Err(format!(
+            "Toolchain '{}' not found, searched: {}",
+            name,
+            searched.join(", ")
+        )
+        .into())
+    }
+
+    pub fn from_yaml(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open toolchain {}: {}", path.display(), e))?;
+        let mut toolchain: Toolchain = serde_yaml::from_reader(file)
+            .map_err(|e| format!("Invalid toolchain {}: {}", path.display(), e))?;
+        if toolchain.name.is_empty() {
+            toolchain.name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        Ok(toolchain)
+    }
+
+    /// 将工具链覆盖到模块的编译器配置上：工具与目标以工具链为准，参数前置合并
+    pub fn apply(&self, compiler: &mut Compiler) {
+        if let Some(cc) = &self.cc {
+            compiler.cc = cc.clone();
+        }
+        if self.ar.is_some() {
+            compiler.ar = self.ar.clone();
+        }
+        if self.linker.is_some() {
+            compiler.linker = self.linker.clone();
+        }
+        if self.objcopy.is_some() {
+            compiler.objcopy = self.objcopy.clone();
+        }
+        if self.size.is_some() {
+            compiler.size = self.size.clone();
+        }
+        if self.gcov.is_some() {
+            compiler.gcov = self.gcov.clone();
+        }
+        if self.runner.is_some() {
+            compiler.runner = self.runner.clone();
+        }
+        if self.target.is_some() {
+            compiler.target = self.target.clone();
+        }
+        if self.sysroot.is_some() {
+            compiler.sysroot = self.sysroot.clone();
+        }
+
+        let mut flags = self.flags.clone();
+        flags.append(&mut compiler.flags);
+        compiler.flags = flags;
+
+        let mut ldflags = self.ldflags.clone();
+        ldflags.append(&mut compiler.ldflags);
+        compiler.ldflags = ldflags;
+    }
+}
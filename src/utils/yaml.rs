@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use crate::module::env::{BuildEnv, Profile};
+use crate::utils::pattern::wildcard_match;
+use crate::utils::shell;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     error::Error,
     path::{Path, PathBuf},
@@ -7,8 +10,16 @@ use std::{
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Dep {
     pub include: Vec<String>,
-    pub mod_deps: Vec<String>,
+    /// 项目内依赖模块，列表形式 `[../net]`，或带版本要求的映射形式 `{ ../net: ">=1.2, <2" }`
+    #[serde(deserialize_with = "de_mod_deps", serialize_with = "ser_mod_deps")]
+    pub mod_deps: Vec<ModDep>,
     pub lib: Vec<String>,
+    /// 通过 pkg-config 查找的系统库，可带版本约束，如 "openssl >= 1.1"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pkg_config: Vec<String>,
+    /// 项目外部的 hk 模块，按本地路径或 git 仓库引入，自动作为本模块的依赖
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external: Vec<External>,
 }
 impl Dep {
     pub fn clean_mod_name(name: &str) -> String {
@@ -18,6 +29,116 @@ impl Dep {
             .unwrap_or("")
             .to_string()
     }
+
+    /// 全部依赖模块名：mod_deps 中的项目内模块 + 外部模块
+    pub fn dep_names(&self) -> Vec<String> {
+        self.mod_deps
+            .iter()
+            .map(|d| d.name())
+            .chain(self.external.iter().map(|e| e.name.clone()))
+            .collect()
+    }
+
+    /// 全部依赖模块名及其版本要求
+    pub fn dep_requirements(&self) -> Vec<(String, Option<&str>)> {
+        self.mod_deps
+            .iter()
+            .map(|d| (d.name(), d.version.as_deref()))
+            .chain(
+                self.external
+                    .iter()
+                    .map(|e| (e.name.clone(), e.version.as_deref())),
+            )
+            .collect()
+    }
+}
+
+/// 项目内依赖模块：模块路径（相对于当前模块目录，如 ../net）与可选的版本要求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModDep {
+    pub path: String,
+    pub version: Option<String>,
+}
+
+impl ModDep {
+    pub fn name(&self) -> String {
+        Dep::clean_mod_name(&self.path)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawModDeps {
+    List(Vec<String>),
+    Map(serde_yaml::Mapping),
+}
+
+fn de_mod_deps<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ModDep>, D::Error> {
+    use serde::de::Error as _;
+    match RawModDeps::deserialize(deserializer)? {
+        RawModDeps::List(list) => Ok(list
+            .into_iter()
+            .map(|path| ModDep {
+                path,
+                version: None,
+            })
+            .collect()),
+        RawModDeps::Map(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let path = key
+                    .as_str()
+                    .ok_or_else(|| D::Error::custom("mod_deps keys must be strings"))?
+                    .to_string();
+                let version = match value {
+                    serde_yaml::Value::Null => None,
+                    serde_yaml::Value::String(s) => Some(s),
+                    serde_yaml::Value::Number(n) => Some(n.to_string()),
+                    _ => {
+                        return Err(D::Error::custom(format!(
+                            "version requirement of {} must be a string",
+                            path
+                        )))
+                    }
+                };
+                Ok(ModDep { path, version })
+            })
+            .collect(),
+    }
+}
+
+/// 没有版本要求时写回列表形式，否则写为映射
+fn ser_mod_deps<S: Serializer>(deps: &[ModDep], serializer: S) -> Result<S::Ok, S::Error> {
+    if deps.iter().all(|d| d.version.is_none()) {
+        serializer.collect_seq(deps.iter().map(|d| &d.path))
+    } else {
+        serializer.collect_map(deps.iter().map(|d| (&d.path, &d.version)))
+    }
+}
+
+/// 外部依赖模块：path、git 与 archive 三选一
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct External {
+    /// 模块名，需与外部模块 config.yaml 中的 name 一致
+    pub name: String,
+    /// 本地路径，相对于声明它的模块目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// git 仓库地址，本地相对路径相对于声明它的模块目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// 分支、标签或提交，缺省为远端默认分支
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// hk package 生成的 .tar.gz 归档，相对于声明它的模块目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// 归档的 sha256，缺省时使用归档旁的 <archive>.sha256 文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// 版本要求，如 ">=1.2, <2"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Compiler {
@@ -30,9 +151,34 @@ pub struct Compiler {
     /// sysroot 路径，用于交叉编译
     #[serde(default)]
     pub sysroot: Option<String>,
-    /// 额外编译参数
+    /// 额外编译参数（编译与链接都会使用）
     #[serde(default)]
     pub flags: Vec<String>,
+    /// 归档工具，默认按 cc 的前缀推断，如 arm-none-eabi-ar
+    #[serde(default)]
+    pub ar: Option<String>,
+    /// 链接器驱动，默认与 cc 相同
+    #[serde(default)]
+    pub linker: Option<String>,
+    /// 仅在链接时使用的参数
+    #[serde(default)]
+    pub ldflags: Vec<String>,
+    /// objcopy 工具，默认按 cc 的前缀推断
+    #[serde(default)]
+    pub objcopy: Option<String>,
+    /// size 工具，默认按 cc 的前缀推断
+    #[serde(default)]
+    pub size: Option<String>,
+    /// 覆盖率工具：gcc 为 gcov，clang 为 llvm-cov，默认按 cc 的前缀与版本推断
+    #[serde(default)]
+    pub gcov: Option<String>,
+    /// 运行器，hk run / hk test 通过它执行产物，如 qemu-aarch64 -L $SYSROOT
+    #[serde(default)]
+    pub runner: Option<String>,
+}
+
+fn default_version() -> String {
+    "0.0.0".to_string()
 }
 
 fn default_cc() -> String {
@@ -46,35 +192,458 @@ impl Default for Compiler {
             target: None,
             sysroot: None,
             flags: Vec::new(),
+            ar: None,
+            linker: None,
+            ldflags: Vec::new(),
+            objcopy: None,
+            size: None,
+            gcov: None,
+            runner: None,
+        }
+    }
+}
+
+impl Compiler {
+    /// 归档工具：未配置时将 cc 的工具名替换为 ar，如 arm-none-eabi-gcc -> arm-none-eabi-ar
+    pub fn archiver(&self) -> String {
+        self.ar.clone().unwrap_or_else(|| self.sibling_tool("ar"))
+    }
+
+    /// objcopy 工具：未配置时按 cc 前缀推断
+    pub fn objcopy(&self) -> String {
+        self.objcopy
+            .clone()
+            .unwrap_or_else(|| self.sibling_tool("objcopy"))
+    }
+
+    /// size 工具：未配置时按 cc 前缀推断
+    pub fn size_tool(&self) -> String {
+        self.size
+            .clone()
+            .unwrap_or_else(|| self.sibling_tool("size"))
+    }
+
+    /// 覆盖率工具：未配置时 gcc 按 cc 前缀推断 gcov，clang 使用 llvm-cov，
+    /// 并沿用 cc 的版本后缀，如 gcc-12 -> gcov-12、clang-17 -> llvm-cov-17
+    pub fn gcov_tool(&self, clang: bool) -> String {
+        if let Some(gcov) = &self.gcov {
+            return gcov.clone();
+        }
+        let file_name = Path::new(&self.cc)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (base, tool) = if clang {
+            ("clang", "llvm-cov")
+        } else {
+            ("gcc", "gcov")
+        };
+        let suffix = file_name
+            .rfind(base)
+            .map(|idx| &file_name[idx + base.len()..])
+            .filter(|rest| {
+                rest.strip_prefix('-').is_some_and(|v| {
+                    !v.is_empty() && v.chars().all(|c| c.is_ascii_digit() || c == '.')
+                })
+            })
+            .unwrap_or("");
+        let tool = format!("{}{}", tool, suffix);
+        if clang {
+            // llvm-cov 不带目标前缀，与 cc 位于同一目录
+            return Path::new(&self.cc)
+                .with_file_name(tool)
+                .to_string_lossy()
+                .into_owned();
+        }
+        self.sibling_tool(&tool)
+    }
+
+    /// 运行器命令行：展开 $SYSROOT / ${SYSROOT} 后按 shell 规则拆分
+    pub fn runner_args(&self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        let Some(runner) = &self.runner else {
+            return Ok(None);
+        };
+        let sysroot = self.sysroot.as_deref().unwrap_or("");
+        let expanded = runner
+            .replace("${SYSROOT}", sysroot)
+            .replace("$SYSROOT", sysroot);
+        let args = shell::split(&expanded)?;
+        if args.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(args))
+    }
+
+    /// 链接器驱动：未配置时使用 cc
+    pub fn linker(&self) -> &str {
+        self.linker.as_deref().unwrap_or(&self.cc)
+    }
+
+    /// 是否为 clang 系编译器（clang 需要显式 --target）
+    pub fn is_clang(&self) -> bool {
+        Path::new(&self.cc)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains("clang"))
+    }
+
+    /// 目标相关的编译参数：clang 的 --target 与 --sysroot
+    pub fn target_args(&self, clang: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(target) = self.target.as_ref().filter(|_| clang) {
+            args.push(format!("--target={}", target));
+        }
+        if let Some(sysroot) = &self.sysroot {
+            args.push("--sysroot".to_string());
+            args.push(sysroot.clone());
+        }
+        args
+    }
+
+    /// 推断与 cc 同前缀的 binutils 工具名
+    pub fn sibling_tool(&self, tool: &str) -> String {
+        let path = Path::new(&self.cc);
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let prefix = match file_name.rfind("-gcc").or_else(|| file_name.rfind("-cc")) {
+            Some(idx) => &file_name[..=idx],
+            None => "",
+        };
+        if prefix.is_empty() {
+            return tool.to_string();
+        }
+        path.with_file_name(format!("{}{}", prefix, tool))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// 运行时检测工具（-fsanitize=）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Thread,
+    Leak,
+}
+
+impl Sanitizer {
+    /// 解析 sanitizer 名，也接受 asan / ubsan / tsan / lsan 简写
+    pub fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value.trim() {
+            "address" | "asan" => Ok(Sanitizer::Address),
+            "undefined" | "ubsan" => Ok(Sanitizer::Undefined),
+            "thread" | "tsan" => Ok(Sanitizer::Thread),
+            "leak" | "lsan" => Ok(Sanitizer::Leak),
+            other => Err(format!(
+                "Unknown sanitizer: {} (expected address, undefined, thread or leak)",
+                other
+            )
+            .into()),
+        }
+    }
+
+    /// 解析逗号分隔的列表，如 address,undefined
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut list = value
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(Self::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        list.sort();
+        list.dedup();
+        Self::check(&list)?;
+        Ok(list)
+    }
+
+    /// 检查组合是否可用：thread 不能与 address / leak 同时启用
+    pub fn check(list: &[Self]) -> Result<(), Box<dyn Error>> {
+        if list.contains(&Sanitizer::Thread)
+            && (list.contains(&Sanitizer::Address) || list.contains(&Sanitizer::Leak))
+        {
+            return Err("The thread sanitizer cannot be combined with address or leak".into());
+        }
+        Ok(())
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
         }
     }
 }
 
+/// hk coverage 的最低覆盖率（百分比），低于时命令失败
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CoverageThreshold {
+    #[serde(default)]
+    pub lines: Option<f64>,
+    #[serde(default)]
+    pub branches: Option<f64>,
+}
+
+/// 警告级别
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningLevel {
+    /// 关闭所有警告（-w）
+    None,
+    /// 编译器默认
+    #[default]
+    Default,
+    /// -Wall
+    All,
+    /// -Wall -Wextra
+    Extra,
+}
+
+/// 警告视为错误：`deny: true` 表示全部，`deny: [return-type]` 表示列出的警告
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Deny {
+    All(bool),
+    List(Vec<String>),
+}
+
+impl Default for Deny {
+    fn default() -> Self {
+        Deny::All(false)
+    }
+}
+
+/// 模块的警告策略
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Warnings {
+    #[serde(default)]
+    pub level: WarningLevel,
+    #[serde(default)]
+    pub deny: Deny,
+    /// 关闭的警告，如 unused-parameter
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl Warnings {
+    pub fn is_default(&self) -> bool {
+        *self == Warnings::default()
+    }
+
+    /// 转换为编译参数；deny_all 为 --deny-warnings，clang 需忽略未知的警告名
+    pub fn flags(&self, clang: bool, deny_all: bool) -> Vec<String> {
+        // 允许写成 unused-parameter、Wunused-parameter 或 -Wunused-parameter
+        let name = |w: &String| {
+            w.trim_start_matches('-')
+                .trim_start_matches('W')
+                .to_string()
+        };
+        let mut flags: Vec<String> = match self.level {
+            WarningLevel::None => vec!["-w".to_string()],
+            WarningLevel::Default => Vec::new(),
+            WarningLevel::All => vec!["-Wall".to_string()],
+            WarningLevel::Extra => vec!["-Wall".to_string(), "-Wextra".to_string()],
+        };
+        match &self.deny {
+            _ if deny_all => flags.push("-Werror".to_string()),
+            Deny::All(true) => flags.push("-Werror".to_string()),
+            Deny::All(false) => {}
+            Deny::List(list) => flags.extend(list.iter().map(|w| format!("-Werror={}", name(w)))),
+        }
+        flags.extend(self.allow.iter().map(|w| format!("-Wno-{}", name(w))));
+        let named = !self.allow.is_empty() || matches!(&self.deny, Deny::List(l) if !l.is_empty());
+        if clang && named {
+            flags.push("-Wno-unknown-warning-option".to_string());
+        }
+        flags
+    }
+}
+
+/// 裸机固件输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirmwareFormat {
+    Bin,
+    Hex,
+}
+
+impl FirmwareFormat {
+    /// 对应的 objcopy -O 参数
+    pub fn bfd_name(&self) -> &'static str {
+        match self {
+            FirmwareFormat::Bin => "binary",
+            FirmwareFormat::Hex => "ihex",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FirmwareFormat::Bin => "bin",
+            FirmwareFormat::Hex => "hex",
+        }
+    }
+}
+
+/// 裸机/嵌入式固件构建配置
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Firmware {
+    /// 链接脚本（相对模块根目录）
+    #[serde(default)]
+    pub linker_script: Option<String>,
+    /// 启动文件（相对模块根目录，.c/.s/.S），链接进每个可执行文件
+    #[serde(default)]
+    pub startup: Vec<String>,
+    /// 编译时加 -ffreestanding
+    #[serde(default)]
+    pub freestanding: bool,
+    /// 链接时加 -nostdlib
+    #[serde(default)]
+    pub nostdlib: bool,
+    /// 生成 <exe>.map 链接映射文件
+    #[serde(default)]
+    pub map: bool,
+    /// 链接后由 objcopy 生成的镜像格式
+    #[serde(default)]
+    pub outputs: Vec<FirmwareFormat>,
+    /// 链接后输出 size 报告
+    #[serde(default)]
+    pub size: bool,
+}
+
+/// 条件块的匹配条件，未填写的字段视为任意匹配
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct When {
+    /// 目标三元组通配符，如 aarch64-*
+    #[serde(default)]
+    pub target: Option<String>,
+    /// 构建配置档：debug / release，其他取值在加载配置时报错
+    #[serde(default)]
+    pub profile: Option<Profile>,
+    /// 主机三元组通配符，如 x86_64-*
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+impl When {
+    /// 判断条件是否满足当前目标与构建环境
+    pub fn matches(&self, target: &str, env: &BuildEnv) -> bool {
+        let target_ok = self
+            .target
+            .as_deref()
+            .is_none_or(|pat| wildcard_match(pat, target));
+        let profile_ok = self.profile.is_none_or(|p| p == env.profile);
+        let host_ok = self
+            .host
+            .as_deref()
+            .is_none_or(|pat| wildcard_match(pat, &env.host));
+        target_ok && profile_ok && host_ok
+    }
+}
+
+/// 条件配置块：满足 `when` 时合并到模块配置中
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Conditional {
+    pub when: When,
+    #[serde(default)]
+    pub premacro: Vec<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub lib: Vec<String>,
+    /// 需要排除的源文件（相对模块根目录，支持通配符）
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub name: String,
+    /// 模块版本，用于打包与导出的包文件
+    #[serde(default = "default_version")]
+    pub version: String,
     pub std: String,
     pub premacro: Vec<String>,
     pub dep: Dep,
     #[serde(default)]
     pub compiler: Compiler,
+    /// 警告策略
+    #[serde(default, skip_serializing_if = "Warnings::is_default")]
+    pub warnings: Warnings,
+    /// 启用的 sanitizer，任一模块配置后对整个依赖图生效
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sanitize: Vec<Sanitizer>,
+    /// hk coverage 的最低覆盖率
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<CoverageThreshold>,
+    /// 排除的源文件（相对模块根目录，支持通配符）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// 裸机固件配置，存在时按固件方式链接
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<Firmware>,
+    /// 按目标/配置档/主机生效的条件块
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditional: Vec<Conditional>,
 }
 
 impl Config {
     pub fn new() -> Self {
         Config {
             name: "app".to_string(),
+            version: "0.1.0".to_string(),
             std: "c99".to_string(),
             premacro: Vec::new(),
             dep: Dep {
                 include: Vec::new(),
                 mod_deps: Vec::new(),
                 lib: Vec::new(),
+                pkg_config: Vec::new(),
+                external: Vec::new(),
             },
             compiler: Compiler::default(),
+            warnings: Warnings::default(),
+            sanitize: Vec::new(),
+            coverage: None,
+            exclude: Vec::new(),
+            firmware: None,
+            conditional: Vec::new(),
         }
     }
 
+    /// 目标三元组：未配置时使用主机三元组
+    pub fn target<'a>(&'a self, env: &'a BuildEnv) -> &'a str {
+        self.compiler.target.as_deref().unwrap_or(&env.host)
+    }
+
+    /// 求值所有条件块，将满足条件的块合并到基础配置中
+    pub fn apply_conditionals(&mut self, env: &BuildEnv) {
+        let target = self.target(env).to_string();
+        let matched: Vec<Conditional> = self
+            .conditional
+            .iter()
+            .filter(|c| c.when.matches(&target, env))
+            .cloned()
+            .collect();
+
+        for cond in matched {
+            self.premacro.extend(cond.premacro);
+            self.compiler.flags.extend(cond.flags);
+            self.dep.include.extend(cond.include);
+            self.dep.lib.extend(cond.lib);
+            self.exclude.extend(cond.exclude);
+        }
+    }
+
+    /// 判断源文件（相对模块根目录的路径）是否被排除
+    pub fn is_excluded(&self, rel_path: &str) -> bool {
+        self.exclude.iter().any(|pat| wildcard_match(pat, rel_path))
+    }
+
     pub fn from_yaml(path: &PathBuf) -> Result<Self, Box<dyn Error>> {
         let file = std::fs::File::open(path)?;
         let config: Config = serde_yaml::from_reader(file)?;
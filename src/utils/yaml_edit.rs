@@ -0,0 +1,512 @@
+use std::error::Error;
+
+/// dep.mod_deps 在 config.yaml 文本中的位置
+enum ModDepsForm {
+    /// 单行 flow 形式：所在行与 `[..]` / `{..}` 在行内的字节区间（含括号）
+    Flow {
+        line: usize,
+        start: usize,
+        end: usize,
+    },
+    /// 块形式：键所在行、键的缩进，以及各条目（起止行、名称）
+    Block {
+        line: usize,
+        indent: usize,
+        seq: bool,
+        entries: Vec<BlockEntry>,
+    },
+}
+
+struct BlockEntry {
+    start: usize,
+    end: usize,
+    name: String,
+}
+
+/// 在 config.yaml 文本中追加一个 mod_deps 依赖，只改动依赖所在的文本，注释与其余格式保持不变。
+/// 带版本要求且原来是列表形式时，转换为映射形式
+pub fn add_mod_dep(
+    text: &str,
+    path: &str,
+    version: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    match locate(&lines)? {
+        ModDepsForm::Flow { line, start, end } => {
+            let value = &lines[line][start..=end];
+            let inner = &value[1..value.len() - 1];
+            let items = split_items(inner);
+            let map = value.starts_with('{') || version.is_some();
+
+            let new_value = if value.starts_with('[') && map {
+                // 列表转换为映射，已有条目不带版本要求
+                let mut entries: Vec<String> = items
+                    .iter()
+                    .map(|&(s, e)| format!("{}: null", &inner[s..e]))
+                    .collect();
+                entries.push(map_entry(path, version));
+                format!("{{{}}}", entries.join(", "))
+            } else {
+                let entry = if map {
+                    map_entry(path, version)
+                } else {
+                    quote_key(path)
+                };
+                match items.last() {
+                    Some(&(_, last)) => format!(
+                        "{}{}, {}{}{}",
+                        &value[..1],
+                        &inner[..last],
+                        entry,
+                        &inner[last..],
+                        &value[value.len() - 1..]
+                    ),
+                    None => format!("{}{}{}", &value[..1], entry, &value[value.len() - 1..]),
+                }
+            };
+            lines[line].replace_range(start..=end, &new_value);
+        }
+        ModDepsForm::Block {
+            line,
+            indent,
+            seq,
+            entries,
+        } => {
+            let last = entries.last().map(|e| e.end).unwrap_or(line + 1);
+            let entry_indent = match entries.first() {
+                Some(e) => indent_of(&lines[e.start]),
+                None => indent + 2,
+            };
+            if seq && version.is_some() {
+                // 块列表转换为块映射
+                let child = " ".repeat(indent + 2);
+                for entry in &entries {
+                    if entry.end - entry.start != 1 {
+                        return Err(
+                            "mod_deps entries span several lines, edit config.yaml by hand".into(),
+                        );
+                    }
+                    let comment = comment_of(&lines[entry.start]);
+                    lines[entry.start] = format!("{}{}:{}", child, quote_key(&entry.name), comment);
+                }
+                lines.insert(last, format!("{}{}", child, map_entry(path, version)));
+            } else if seq {
+                lines.insert(
+                    last,
+                    format!("{}- {}", " ".repeat(entry_indent), quote_key(path)),
+                );
+            } else {
+                let entry = match version {
+                    Some(_) => map_entry(path, version),
+                    None => format!("{}:", quote_key(path)),
+                };
+                lines.insert(last, format!("{}{}", " ".repeat(entry_indent), entry));
+            }
+        }
+    }
+    Ok(join_lines(&lines, text))
+}
+
+/// 从 config.yaml 文本中删除名称（路径的最后一段）为 name 的 mod_deps 依赖，返回新文本与是否找到
+pub fn remove_mod_dep(text: &str, name: &str) -> Result<(String, bool), Box<dyn Error>> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    match locate(&lines)? {
+        ModDepsForm::Flow { line, start, end } => {
+            let value = lines[line][start..=end].to_string();
+            let inner = &value[1..value.len() - 1];
+            let items = split_items(inner);
+            let Some(idx) = items
+                .iter()
+                .position(|&(s, e)| dep_name(&item_key(&inner[s..e])) == name)
+            else {
+                return Ok((text.to_string(), false));
+            };
+
+            // 连同相邻的逗号一起删除，其余条目的格式不变
+            let new_inner = if items.len() == 1 {
+                String::new()
+            } else if idx + 1 < items.len() {
+                format!("{}{}", &inner[..items[idx].0], &inner[items[idx + 1].0..])
+            } else {
+                format!("{}{}", &inner[..items[idx - 1].1], &inner[items[idx].1..])
+            };
+            let new_value = format!("{}{}{}", &value[..1], new_inner, &value[value.len() - 1..]);
+            lines[line].replace_range(start..=end, &new_value);
+        }
+        ModDepsForm::Block {
+            line,
+            indent,
+            seq,
+            entries,
+        } => {
+            let Some(entry) = entries.iter().find(|e| dep_name(&e.name) == name) else {
+                return Ok((text.to_string(), false));
+            };
+            lines.drain(entry.start..entry.end);
+            // 删除最后一个条目后写为空列表/映射，避免 mod_deps 变为 null
+            if entries.len() == 1 {
+                let comment = comment_of(&lines[line]);
+                let empty = if seq { "[]" } else { "{}" };
+                lines[line] = format!("{}mod_deps: {}{}", " ".repeat(indent), empty, comment);
+            }
+        }
+    }
+    Ok((join_lines(&lines, text), true))
+}
+
+/// 定位 dep.mod_deps：dep 可以是单行 flow 映射或块映射
+fn locate(lines: &[String]) -> Result<ModDepsForm, Box<dyn Error>> {
+    let dep = lines
+        .iter()
+        .position(|l| l.starts_with("dep:"))
+        .ok_or("config.yaml has no `dep` section")?;
+    let rest = strip_comment(&lines[dep]["dep:".len()..]).trim();
+
+    if rest.starts_with('{') {
+        let line = &lines[dep];
+        let key = line
+            .find("mod_deps")
+            .ok_or("`dep` has no `mod_deps` entry")?;
+        let (start, end) = flow_value(line, key + "mod_deps".len())?;
+        return Ok(ModDepsForm::Flow {
+            line: dep,
+            start,
+            end,
+        });
+    }
+    if !rest.is_empty() {
+        return Err("Unsupported `dep` format, edit config.yaml by hand".into());
+    }
+
+    // 块映射：dep 的子项直到下一个顶层键为止
+    let children = lines[dep + 1..]
+        .iter()
+        .position(|l| !is_blank_or_comment(l) && indent_of(l) == 0)
+        .map_or(lines.len(), |n| dep + 1 + n);
+    let key_line = (dep + 1..children)
+        .find(|&i| lines[i].trim_start().starts_with("mod_deps:"))
+        .ok_or("`dep` has no `mod_deps` entry")?;
+    let indent = indent_of(&lines[key_line]);
+    let after = lines[key_line].find("mod_deps:").unwrap_or(0) + "mod_deps:".len();
+    if !strip_comment(&lines[key_line][after..]).trim().is_empty() {
+        let (start, end) = flow_value(&lines[key_line], after)?;
+        return Ok(ModDepsForm::Flow {
+            line: key_line,
+            start,
+            end,
+        });
+    }
+
+    // 块条目：比键缩进更深的行，或与键同缩进的 "- " 行
+    let mut entries: Vec<BlockEntry> = Vec::new();
+    let mut entry_indent = None;
+    let mut seq = false;
+    for (i, line) in lines.iter().enumerate().take(children).skip(key_line + 1) {
+        if is_blank_or_comment(line) {
+            continue;
+        }
+        let ind = indent_of(line);
+        let trimmed = line.trim_start();
+        let is_item = trimmed.starts_with("- ") || trimmed == "-";
+        if ind < indent || (ind == indent && !is_item) {
+            break;
+        }
+        match entry_indent {
+            Some(e) if ind > e => {
+                if let Some(last) = entries.last_mut() {
+                    last.end = i + 1;
+                }
+            }
+            _ => {
+                entry_indent = Some(ind);
+                seq = is_item;
+                let name = if is_item {
+                    unquote(strip_comment(&trimmed[1..]).trim())
+                } else {
+                    item_key(strip_comment(trimmed).trim())
+                };
+                entries.push(BlockEntry {
+                    start: i,
+                    end: i + 1,
+                    name,
+                });
+            }
+        }
+    }
+    Ok(ModDepsForm::Block {
+        line: key_line,
+        indent,
+        seq,
+        entries,
+    })
+}
+
+/// 在行内 from 之后查找 `: [..]` 或 `: {..}`，返回值的起止字节位置（含括号）
+fn flow_value(line: &str, from: usize) -> Result<(usize, usize), Box<dyn Error>> {
+    let rest = &line[from..];
+    let offset = rest.len() - rest.trim_start_matches([' ', ':']).len();
+    let start = from + offset;
+    if !matches!(line[start..].chars().next(), Some('[') | Some('{')) {
+        return Err("`mod_deps` must be a list or a mapping".into());
+    }
+
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in line[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((start, start + i));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("`mod_deps` spans several lines, edit config.yaml by hand".into())
+}
+
+/// 按顶层逗号切分 flow 集合的内容，返回各条目去掉首尾空白后的字节区间
+fn split_items(inner: &str) -> Vec<(usize, usize)> {
+    let mut items = Vec::new();
+    let mut push = |s: usize, e: usize| {
+        let part = &inner[s..e];
+        let lead = part.len() - part.trim_start().len();
+        let trail = part.len() - part.trim_end().len();
+        if lead + trail < part.len() {
+            items.push((s + lead, e - trail));
+        }
+    };
+
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                push(start, i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    push(start, inner.len());
+    items
+}
+
+/// 条目的键：映射条目取冒号前的部分，列表条目即其本身
+fn item_key(item: &str) -> String {
+    let key = if item.starts_with('"') || item.starts_with('\'') {
+        let q = &item[..1];
+        match item[1..].find(q) {
+            Some(end) => &item[..end + 2],
+            None => item,
+        }
+    } else {
+        match item.find(": ") {
+            Some(pos) => &item[..pos],
+            None => item.strip_suffix(':').unwrap_or(item),
+        }
+    };
+    unquote(key.trim())
+}
+
+fn dep_name(path: &str) -> String {
+    crate::utils::yaml::Dep::clean_mod_name(path)
+}
+
+fn map_entry(path: &str, version: Option<&str>) -> String {
+    match version {
+        Some(v) => format!("{}: \"{}\"", quote_key(path), v.replace('"', "\\\"")),
+        None => format!("{}: null", quote_key(path)),
+    }
+}
+
+/// 仅含普通路径字符时原样写出，否则加双引号
+fn quote_key(path: &str) -> String {
+    if !path.is_empty()
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-/".contains(c))
+    {
+        path.to_string()
+    } else {
+        format!("\"{}\"", path.replace('"', "\\\""))
+    }
+}
+
+fn unquote(s: &str) -> String {
+    let quoted = s.len() >= 2
+        && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')));
+    if quoted {
+        s[1..s.len() - 1].to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// 行内注释的起始位置：引号外、位于行首或空白之后的 #
+fn comment_start(s: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut prev_space = true;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') if prev_space => return Some(i),
+            _ => {}
+        }
+        prev_space = c.is_whitespace();
+    }
+    None
+}
+
+fn strip_comment(s: &str) -> &str {
+    comment_start(s).map_or(s, |i| &s[..i])
+}
+
+/// 行尾注释（含前导空白），没有注释时为空串
+fn comment_of(line: &str) -> String {
+    match comment_start(line) {
+        Some(i) => {
+            let code = line[..i].trim_end();
+            line[code.len()..].to_string()
+        }
+        None => String::new(),
+    }
+}
+
+fn join_lines(lines: &[String], original: &str) -> String {
+    let mut text = lines.join("\n");
+    if original.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mod_deps: &str) -> String {
+        format!(
+            "name: app # the app\nversion: 0.1.0\ndep:\n  include: []\n{}  lib: [m]\ncompiler:\n  cc: gcc\n",
+            mod_deps
+        )
+    }
+
+    #[test]
+    fn add_to_flow_list() {
+        let text = config("  mod_deps: [../core, \"../my net\"] # deps\n");
+        let new = add_mod_dep(&text, "../util", None).unwrap();
+        assert_eq!(
+            new,
+            config("  mod_deps: [../core, \"../my net\", ../util] # deps\n")
+        );
+
+        let empty = config("  mod_deps: []\n");
+        let new = add_mod_dep(&empty, "../util", None).unwrap();
+        assert_eq!(new, config("  mod_deps: [../util]\n"));
+    }
+
+    #[test]
+    fn add_to_block_list() {
+        let text = config("  mod_deps:\n  # project modules\n  - ../core # base\n");
+        let new = add_mod_dep(&text, "../util", None).unwrap();
+        assert_eq!(
+            new,
+            config("  mod_deps:\n  # project modules\n  - ../core # base\n  - ../util\n")
+        );
+    }
+
+    #[test]
+    fn add_with_version_turns_list_into_map() {
+        let text = config("  mod_deps: [../core] # deps\n");
+        let new = add_mod_dep(&text, "../util", Some("^1.2")).unwrap();
+        assert_eq!(
+            new,
+            config("  mod_deps: {../core: null, ../util: \"^1.2\"} # deps\n")
+        );
+
+        let text = config("  mod_deps:\n  - ../core # base\n  - ../net\n");
+        let new = add_mod_dep(&text, "../util", Some(">=1.0, <2")).unwrap();
+        assert_eq!(
+            new,
+            config("  mod_deps:\n    ../core: # base\n    ../net:\n    ../util: \">=1.0, <2\"\n")
+        );
+    }
+
+    #[test]
+    fn add_to_block_map_keeps_comments() {
+        let text = config("  mod_deps: # versioned\n    ../core: \"^1\" # pinned\n");
+        let new = add_mod_dep(&text, "../util", None).unwrap();
+        assert_eq!(
+            new,
+            config("  mod_deps: # versioned\n    ../core: \"^1\" # pinned\n    ../util:\n")
+        );
+    }
+
+    #[test]
+    fn remove_keeps_other_entries_and_comments() {
+        let text = config("  mod_deps: [../core, ../net, ../util] # deps\n");
+        let (new, found) = remove_mod_dep(&text, "net").unwrap();
+        assert!(found);
+        assert_eq!(new, config("  mod_deps: [../core, ../util] # deps\n"));
+
+        let text = config("  mod_deps:\n  - ../core # base\n  # network\n  - ../net\n");
+        let (new, found) = remove_mod_dep(&text, "core").unwrap();
+        assert!(found);
+        assert_eq!(new, config("  mod_deps:\n  # network\n  - ../net\n"));
+
+        let (same, found) = remove_mod_dep(&text, "util").unwrap();
+        assert!(!found);
+        assert_eq!(same, text);
+    }
+
+    #[test]
+    fn remove_last_entry_leaves_empty_collection() {
+        let text = config("  mod_deps: [../core]\n");
+        let (new, _) = remove_mod_dep(&text, "core").unwrap();
+        assert_eq!(new, config("  mod_deps: []\n"));
+
+        let text = config("  mod_deps: # deps\n  - ../core\n");
+        let (new, _) = remove_mod_dep(&text, "core").unwrap();
+        assert_eq!(new, config("  mod_deps: [] # deps\n"));
+
+        let text = config("  mod_deps:\n    ../core: \"^1\"\n");
+        let (new, _) = remove_mod_dep(&text, "core").unwrap();
+        assert_eq!(new, config("  mod_deps: {}\n"));
+    }
+
+    #[test]
+    fn missing_sections_are_errors() {
+        let no_dep = "name: app\nversion: 0.1.0\n";
+        let err = add_mod_dep(no_dep, "../core", None).unwrap_err();
+        assert!(err.to_string().contains("no `dep` section"), "{}", err);
+
+        let no_mod_deps = "name: app\ndep:\n  include: []\n  lib: []\n";
+        let err = add_mod_dep(no_mod_deps, "../core", None).unwrap_err();
+        assert!(err.to_string().contains("no `mod_deps` entry"), "{}", err);
+        let err = remove_mod_dep(no_mod_deps, "core").unwrap_err();
+        assert!(err.to_string().contains("no `mod_deps` entry"), "{}", err);
+    }
+}